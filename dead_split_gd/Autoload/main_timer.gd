@@ -2,7 +2,6 @@ extends DeadSplitTimer
 
 @warning_ignore("unused_signal")
 signal run_changed
-signal comparison_changed
 
 var autosplitter: Autosplitter = null
 @onready var autosplitter_ticks: Timer = Timer.new()
@@ -10,6 +9,7 @@ var autosplitter: Autosplitter = null
 func _ready() -> void:
 	new_run()
 	self.hotkey_pressed.connect(_hotkey_pressed)
+	self.comparison_changed.connect(_comparison_changed)
 	autosplitter_ticks.wait_time = 1.0/120.0
 	autosplitter_ticks.one_shot = false
 	autosplitter_ticks.timeout.connect(update_autosplitter)
@@ -36,18 +36,22 @@ func _hotkey_pressed(hotkey_id: int) -> void:
 			toggle_pause()
 		8:
 			toggle_timing_method()
+			TimerSettings.rta = get_timing_method() == 0
 		9:
-			var comp_list := get_comparisons()
-			TimerSettings.active_comp_idx = (TimerSettings.active_comp_idx + 1) % comp_list.size()
-			var comp := comp_list[TimerSettings.active_comp_idx]
-			TimerSettings.active_comparison = comp
-			comparison_changed.emit(comp)
+			switch_to_next_comparison()
 		10:
-			var comp_list := get_comparisons()
-			TimerSettings.active_comp_idx = (TimerSettings.active_comp_idx - 1) % comp_list.size()
-			var comp := comp_list[TimerSettings.active_comp_idx]
-			TimerSettings.active_comparison = comp
-			comparison_changed.emit(comp)
+			switch_to_previous_comparison()
+
+# Keeps the saved settings in sync with the comparison livesplit-core is using
+func _comparison_changed(comp: String) -> void:
+	TimerSettings.active_comparison = comp
+	TimerSettings.active_comp_idx = get_comparisons().find(comp)
+
+# Runs can have their own timing method, otherwise the saved one is used
+func sync_timing_method() -> void:
+	if get_default_timing_method() != -1:
+		TimerSettings.rta = get_default_timing_method() == 0
+	set_timing_method(0 if TimerSettings.rta else 1)
 
 func update_autosplitter() -> void:
 	if autosplitter:
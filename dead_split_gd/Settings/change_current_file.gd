@@ -22,11 +22,17 @@ func _on_run_file_chooser_file_selected(path: String) -> void:
 	file_label.text = "Current Splits File: " + path
 	disabled = false
 	text = "Open"
-	MainTimer.try_load_run(TimerSettings.current_file_path)
+	var result := MainTimer.load_run(TimerSettings.current_file_path)
+	if result["ok"]:
+		MainTimer.sync_timing_method()
+		run_saved_label.text = "Loaded " + result["format"] + " splits"
+	else:
+		run_saved_label.text = "Failed to load run - " + result["message"]
+	run_saved_label.show()
 
 func _on_save_run_to_file_pressed() -> void:
-	var success := MainTimer.try_save_run(TimerSettings.current_file_path)
-	run_saved_label.text = "Run saved!" if success else "Failed to save run - check your path is valid"
+	var result := MainTimer.save_run(TimerSettings.current_file_path)
+	run_saved_label.text = "Run saved!" if result["ok"] else "Failed to save run - " + result["message"]
 	run_saved_label.show()
 
 func _on_run_file_chooser_canceled() -> void:
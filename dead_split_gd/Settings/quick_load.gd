@@ -73,7 +73,8 @@ func load_file(path: String) -> void:
 		TimerSettings.timer_theme_path = path
 	elif path.ends_with(".lss"): # run
 		TimerSettings.current_file_path = path
-		MainTimer.try_load_run(TimerSettings.current_file_path)
+		if MainTimer.try_load_run(TimerSettings.current_file_path):
+			MainTimer.sync_timing_method()
 	elif path.ends_with(".tres"):
 		TimerSettings.load_profile(path)
 	
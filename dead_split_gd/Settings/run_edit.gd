@@ -112,8 +112,11 @@ func _on_offset_text_changed(new_text: String) -> void:
 	offset_edit.text = str(editable_run.get_offset())
 
 func _on_add_comp_button_pressed() -> void:
-	editable_run.add_custom_comparison(add_comp_text.text)
-	add_comp_text.text = ""
+	var error: String = editable_run.add_custom_comparison(add_comp_text.text)
+	if error.is_empty():
+		add_comp_text.text = ""
+	else:
+		push_warning(error)
 	update_custom_comparisons_list()
 
 func _on_remove_comp_button_pressed() -> void:
@@ -59,6 +59,7 @@ func _on_save_button_pressed() -> void:
 
 func _on_use_igt_toggle_toggled(toggled_on: bool) -> void:
 	TimerSettings.rta = !toggled_on
+	MainTimer.set_timing_method(0 if TimerSettings.rta else 1)
 
 
 func _on_use_igt_toggle_visibility_changed() -> void:
@@ -56,6 +56,8 @@ func _ready() -> void:
 	# Try to load in any settings
 	TimerSettings.try_load()
 	MainTimer.try_load_run(TimerSettings.current_file_path)
+	MainTimer.set_current_comparison(TimerSettings.active_comparison)
+	MainTimer.sync_timing_method()
 	update_settings()
 	reload_theme()
 	
@@ -65,13 +67,29 @@ func _ready() -> void:
 	MainTimer.comparison_changed.connect(comp_changed)
 	
 	MainTimer.run_changed.emit()
-	timer_phase_changed.connect(func(phase: TimerSettings.TimerPhase):
-		if phase == TimerSettings.TimerPhase.RUNNING:
-			MainTimer.init_game_time()
-		# On reset, save run (make this an optional feature later?)
-		elif phase == TimerSettings.TimerPhase.NOT_RUNNING:
-			MainTimer.try_save_run(TimerSettings.current_file_path)
+	# Offer to pick the last attempt back up if the timer closed while it was running
+	if MainTimer.has_recoverable_attempt():
+		ask_recover_attempt()
+	else:
+		MainTimer.discard_recoverable_attempt()
+	# On reset, the run is saved back to the file it was loaded from (make this an optional feature later?)
+	MainTimer.autosave = true
+
+func ask_recover_attempt() -> void:
+	var dialog := ConfirmationDialog.new()
+	dialog.title = "Recover Attempt"
+	dialog.dialog_text = "The timer closed during an attempt at " + \
+		TimerSettings.round_off(MainTimer.get_recoverable_attempt_time()) + ". Pick it back up?"
+	dialog.ok_button_text = "Recover"
+	dialog.cancel_button_text = "Discard"
+	dialog.confirmed.connect(func():
+		if MainTimer.recover_attempt():
+			notification_popup.set_text("Recovered attempt")
+			notification_popup.flash()
 	)
+	dialog.canceled.connect(MainTimer.discard_recoverable_attempt)
+	add_child(dialog)
+	dialog.popup_centered()
 
 func add_element(element: TimerElement) -> void:
 	element.root = self
@@ -4,10 +4,10 @@ use godot::builtin::{Dictionary, Variant, VariantType};
 use livesplit_auto_splitting::{settings, AutoSplitter, Runtime};
 use livesplit_core::SharedTimer;
 
-use crate::{timer_read, timer_write};
+use crate::{events::{self, EventQueue}, timer_read, timer_write};
 
 // deplorable.
-struct TimerBox(SharedTimer);
+struct TimerBox(SharedTimer, EventQueue);
 
 // more deplorable.
 impl livesplit_auto_splitting::Timer for TimerBox {
@@ -21,23 +21,33 @@ impl livesplit_auto_splitting::Timer for TimerBox {
     }
 
     fn start(&mut self) {
-        timer_write(&mut self.0).start();
+        events::act(&self.0, &self.1, false, |timer| {
+            let _ = timer.start();
+        });
     }
 
     fn split(&mut self) {
-        timer_write(&mut self.0).split();
+        events::act(&self.0, &self.1, false, |timer| {
+            let _ = timer.split();
+        });
     }
 
     fn skip_split(&mut self) {
-        timer_write(&mut self.0).skip_split();
+        events::act(&self.0, &self.1, false, |timer| {
+            let _ = timer.skip_split();
+        });
     }
 
     fn undo_split(&mut self) {
-        timer_write(&mut self.0).undo_split();
+        events::act(&self.0, &self.1, false, |timer| {
+            let _ = timer.undo_split();
+        });
     }
 
     fn reset(&mut self) {
-        timer_write(&mut self.0).reset(true);
+        events::act(&self.0, &self.1, true, |timer| {
+            let _ = timer.reset(true);
+        });
     }
 
     fn set_game_time(&mut self, time: livesplit_auto_splitting::time::Duration) {
@@ -96,7 +106,7 @@ fn autosplitter_thread(auto_splitter: Arc<AutoSplitter<TimerBox>>) {
 }
     
 impl AutosplitterManager {
-    pub fn new(timer: SharedTimer, wasm_file_path: String) -> Result<Self, ()> {
+    pub fn new(timer: SharedTimer, events: EventQueue, wasm_file_path: String) -> Result<Self, ()> {
         let module = std::fs::read(wasm_file_path).map_err(|_| ())?;
         let mut config = livesplit_auto_splitting::Config::default();
         config.optimize = true;
@@ -104,7 +114,7 @@ impl AutosplitterManager {
         config.debug_info = false;
         let runtime = Runtime::new(config).expect("Failed to create autosplitter runtime");
         let auto_splitter = runtime.compile(module.as_slice()).map_err(|_| ())?
-            .instantiate(TimerBox(timer), None, None).map_err(|_| ())?;
+            .instantiate(TimerBox(timer, events), None, None).map_err(|_| ())?;
 
         let auto_splitter_arc: Arc<AutoSplitter<TimerBox>> = auto_splitter.into();
 
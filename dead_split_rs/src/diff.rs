@@ -0,0 +1,324 @@
+use std::collections::HashSet;
+
+use godot::builtin::Dictionary;
+use livesplit_core::{
+    comparison::personal_best,
+    run::Attempt,
+    timing::formatter::{Regular, TimeFormatter},
+    Run, TimeSpan, TimingMethod,
+};
+
+use crate::segments;
+
+// A difference between two copies of a run, "ours" being the run being edited and "theirs" the other file.
+// Handed to godot as { "kind": String, "index": int, "message": String }, index being the segment (-1 if none).
+// kind is one of "segment_added", "segment_removed", "segment_renamed", "gold_changed", "pb_changed",
+// "attempt_count_changed", "attempts_only_ours", "attempts_only_theirs", "comparison_added",
+// "comparison_removed" or "comparison_changed". "added" means only theirs has it.
+pub struct Difference {
+    kind: &'static str,
+    index: i64,
+    message: String,
+}
+
+impl Difference {
+    fn new(kind: &'static str, index: Option<usize>, message: String) -> Self {
+        Self {
+            kind,
+            index: index.map_or(-1, |i| i as i64),
+            message,
+        }
+    }
+
+    pub fn to_dict(&self) -> Dictionary {
+        let mut dict = Dictionary::new();
+        dict.set("kind", self.kind);
+        dict.set("index", self.index);
+        dict.set("message", self.message.as_str());
+        dict
+    }
+}
+
+fn format_time(t: Option<TimeSpan>) -> String {
+    Regular::new().format(t).to_string()
+}
+
+fn method_name(method: TimingMethod) -> &'static str {
+    match method {
+        TimingMethod::RealTime => "real time",
+        TimingMethod::GameTime => "game time",
+    }
+}
+
+// Attempts are the same attempt if they started at the same moment. Old attempts without a start time
+// can only be matched up by their id.
+fn attempt_key(attempt: &Attempt) -> (Option<i64>, Option<i32>) {
+    match attempt.started() {
+        Some(started) => (Some(started.time.unix_timestamp()), None),
+        None => (None, Some(attempt.index())),
+    }
+}
+
+pub fn diff(ours: &Run, theirs: &Run) -> Vec<Difference> {
+    let mut found = Vec::new();
+
+    for idx in 0..ours.len().max(theirs.len()) {
+        match (ours.segments().get(idx), theirs.segments().get(idx)) {
+            (Some(a), Some(b)) => {
+                if a.name() != b.name() {
+                    found.push(Difference::new(
+                        "segment_renamed",
+                        Some(idx),
+                        format!("\"{}\" is \"{}\" in theirs", a.name(), b.name()),
+                    ));
+                }
+                for method in TimingMethod::all() {
+                    let (gold_a, gold_b) = (a.best_segment_time()[method], b.best_segment_time()[method]);
+                    if gold_a != gold_b {
+                        found.push(Difference::new(
+                            "gold_changed",
+                            Some(idx),
+                            format!(
+                                "The {} gold for \"{}\" is {} in ours and {} in theirs",
+                                method_name(method),
+                                a.name(),
+                                format_time(gold_a),
+                                format_time(gold_b)
+                            ),
+                        ));
+                    }
+                }
+            }
+            (Some(a), None) => found.push(Difference::new(
+                "segment_removed",
+                Some(idx),
+                format!("\"{}\" is only in ours", a.name()),
+            )),
+            (None, Some(b)) => found.push(Difference::new(
+                "segment_added",
+                Some(idx),
+                format!("\"{}\" is only in theirs", b.name()),
+            )),
+            (None, None) => {}
+        }
+    }
+
+    for method in TimingMethod::all() {
+        let pb = |run: &Run| run.segments().last().and_then(|s| s.personal_best_split_time()[method]);
+        if pb(ours) != pb(theirs) {
+            found.push(Difference::new(
+                "pb_changed",
+                None,
+                format!(
+                    "The {} PB is {} in ours and {} in theirs",
+                    method_name(method),
+                    format_time(pb(ours)),
+                    format_time(pb(theirs))
+                ),
+            ));
+        }
+    }
+
+    if ours.attempt_count() != theirs.attempt_count() {
+        found.push(Difference::new(
+            "attempt_count_changed",
+            None,
+            format!(
+                "{} attempts in ours and {} in theirs",
+                ours.attempt_count(),
+                theirs.attempt_count()
+            ),
+        ));
+    }
+    let our_attempts: HashSet<_> = ours.attempt_history().iter().map(attempt_key).collect();
+    let their_attempts: HashSet<_> = theirs.attempt_history().iter().map(attempt_key).collect();
+    let only_ours = our_attempts.difference(&their_attempts).count();
+    let only_theirs = their_attempts.difference(&our_attempts).count();
+    if only_ours > 0 {
+        found.push(Difference::new(
+            "attempts_only_ours",
+            None,
+            format!("{only_ours} attempts are only in ours"),
+        ));
+    }
+    if only_theirs > 0 {
+        found.push(Difference::new(
+            "attempts_only_theirs",
+            None,
+            format!("{only_theirs} attempts are only in theirs"),
+        ));
+    }
+
+    // Personal Best is covered by the golds and pb_changed above
+    let our_comparisons: Vec<&String> = ours.custom_comparisons().iter().filter(|c| *c != personal_best::NAME).collect();
+    let their_comparisons: Vec<&String> = theirs.custom_comparisons().iter().filter(|c| *c != personal_best::NAME).collect();
+    for comparison in &our_comparisons {
+        if !their_comparisons.contains(comparison) {
+            found.push(Difference::new(
+                "comparison_removed",
+                None,
+                format!("\"{comparison}\" is only in ours"),
+            ));
+            continue;
+        }
+        let changed = ours
+            .segments()
+            .iter()
+            .zip(theirs.segments())
+            .filter(|(a, b)| a.comparison(comparison) != b.comparison(comparison))
+            .count();
+        if changed > 0 {
+            found.push(Difference::new(
+                "comparison_changed",
+                None,
+                format!("{changed} split times in \"{comparison}\" differ"),
+            ));
+        }
+    }
+    for comparison in &their_comparisons {
+        if !our_comparisons.contains(comparison) {
+            found.push(Difference::new(
+                "comparison_added",
+                None,
+                format!("\"{comparison}\" is only in theirs"),
+            ));
+        }
+    }
+
+    found
+}
+
+// Adds their attempts that ours doesn't have, along with their segment times, for two copies of the same run
+// (e.g. from two PCs). The added attempts get new ids after ours. Golds are improved by the added segment
+// times, but the PB is left alone since their splits may not match ours. Returns how many attempts were added.
+pub fn merge_history(ours: &mut Run, theirs: &Run) -> Result<usize, String> {
+    if ours.len() != theirs.len() {
+        return Err(String::from("The runs have a different number of segments"));
+    }
+
+    let known: HashSet<_> = ours.attempt_history().iter().map(attempt_key).collect();
+    let mut next_id = ours.attempt_history().iter().map(|a| a.index()).max().unwrap_or(0).max(0) + 1;
+    let mut added = 0;
+
+    for attempt in theirs.attempt_history() {
+        if known.contains(&attempt_key(attempt)) {
+            continue;
+        }
+        ours.add_attempt_with_index(
+            attempt.time(),
+            next_id,
+            attempt.started(),
+            attempt.ended(),
+            attempt.pause_time(),
+        );
+
+        for idx in 0..ours.len() {
+            let Some(time) = theirs.segment(idx).segment_history().get(attempt.index()) else {
+                break;
+            };
+            ours.segment_mut(idx).segment_history_mut().insert(next_id, time);
+            for method in TimingMethod::all() {
+                segments::improve_gold(ours, idx, next_id, method);
+            }
+        }
+
+        next_id += 1;
+        added += 1;
+    }
+
+    ours.set_attempt_count(ours.attempt_count() + added as u32);
+    ours.regenerate_comparisons();
+    Ok(added)
+}
+
+#[cfg(test)]
+mod tests {
+    use livesplit_core::Segment;
+
+    use super::*;
+    use crate::test_helpers::{history, run_with_attempts, seconds, started};
+
+    const NAMES: &[&str] = &["One", "Two"];
+
+    #[test]
+    fn attempts_are_matched_by_start_time() {
+        let (a, b, c) = (started(30.0), started(20.0), started(10.0));
+        let mut ours = run_with_attempts(
+            NAMES,
+            &[
+                (1, Some(a), &[Some(10.0), Some(20.0)]),
+                (2, Some(b), &[Some(11.0), Some(21.0)]),
+            ],
+        );
+        // Their copy numbered the shared attempt differently
+        let theirs = run_with_attempts(
+            NAMES,
+            &[
+                (1, Some(b), &[Some(11.0), Some(21.0)]),
+                (2, Some(c), &[Some(12.0), Some(22.0)]),
+            ],
+        );
+
+        assert_eq!(merge_history(&mut ours, &theirs), Ok(1));
+        assert_eq!(ours.attempt_history().len(), 3);
+        assert_eq!(ours.attempt_count(), 3);
+        let added = ours.attempt_history().last().unwrap();
+        assert_eq!(added.started().map(|s| s.time.unix_timestamp()), Some(c.time.unix_timestamp()));
+
+        // Merging again finds nothing new
+        assert_eq!(merge_history(&mut ours, &theirs), Ok(0));
+    }
+
+    #[test]
+    fn added_attempts_get_ids_after_ours() {
+        let mut ours = run_with_attempts(
+            NAMES,
+            &[(1, None, &[Some(10.0), Some(20.0)]), (4, None, &[Some(11.0), Some(21.0)])],
+        );
+        // Without start times attempts are matched by id, so only 2 and 3 are new
+        let theirs = run_with_attempts(
+            NAMES,
+            &[
+                (1, None, &[Some(10.0), Some(20.0)]),
+                (2, None, &[Some(12.0), Some(22.0)]),
+                (3, None, &[Some(13.0), None]),
+            ],
+        );
+
+        assert_eq!(merge_history(&mut ours, &theirs), Ok(2));
+        let ids: Vec<i32> = ours.attempt_history().iter().map(|a| a.index()).collect();
+        assert_eq!(ids, [1, 4, 5, 6]);
+        assert_eq!(history(&ours, 0, 5), Some(seconds(12.0)));
+        assert_eq!(history(&ours, 1, 5), Some(seconds(22.0)));
+        assert_eq!(history(&ours, 0, 6), Some(seconds(13.0)));
+        assert_eq!(history(&ours, 1, 6), Some(None));
+    }
+
+    #[test]
+    fn times_after_a_skipped_split_are_not_golds() {
+        let mut ours = run_with_attempts(NAMES, &[(1, None, &[Some(10.0), Some(20.0)])]);
+        ours.segment_mut(0).best_segment_time_mut().real_time = seconds(10.0);
+        ours.segment_mut(1).best_segment_time_mut().real_time = seconds(20.0);
+        let theirs = run_with_attempts(
+            NAMES,
+            &[
+                (1, None, &[Some(10.0), Some(20.0)]),
+                (2, None, &[None, Some(15.0)]),
+                (3, None, &[Some(8.0), Some(25.0)]),
+            ],
+        );
+
+        assert_eq!(merge_history(&mut ours, &theirs), Ok(2));
+        // 15 covers both segments
+        assert_eq!(ours.segment(1).best_segment_time().real_time, seconds(20.0));
+        assert_eq!(ours.segment(0).best_segment_time().real_time, seconds(8.0));
+    }
+
+    #[test]
+    fn runs_need_the_same_segments() {
+        let mut ours = run_with_attempts(NAMES, &[]);
+        let mut theirs = run_with_attempts(NAMES, &[]);
+        theirs.push_segment(Segment::new("Three"));
+        assert!(merge_history(&mut ours, &theirs).is_err());
+    }
+}
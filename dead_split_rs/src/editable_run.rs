@@ -1,23 +1,81 @@
-use std::str::FromStr;
+use std::{path::Path, str::FromStr};
 
 use godot::prelude::*;
-use livesplit_core::{Run, Segment, TimeSpan};
+use godot::classes::ImageTexture;
+use livesplit_core::{
+    run::{editor::cleaning::CleanUp, Editor},
+    comparison::personal_best,
+    settings::Image,
+    Run, TimeSpan, TimingMethod,
+};
+
+use crate::{
+    diff, export,
+    generators::{self, GeneratorSpec},
+    icons, persistence, run_settings, segments,
+    subsplits::{self, SplitNode},
+    validation,
+};
 
 // Godot class wrapper for a run so it can be edited through godot code, then put back into the timer.
+// Edits go through livesplit-core's run editor, which keeps segment history, golds and comparisons consistent.
 #[derive(GodotClass)]
 #[class(no_init)]
 pub struct EditableRun {
-    run: Run,
+    editor: Editor,
+    // Sum of best cleaning. The cleaner borrows the editor so it's made again for every call,
+    // skipping the candidates the user already rejected. Candidates are kept as the (segment, attempt id)
+    // of the history entry they'd remove, see clean_up_key.
+    cleaner_rejected: Vec<(usize, i32)>,
+    pending_clean_up: Option<(usize, i32)>,
 }
 
 // NOT godot api functions, used within rust to interact with the object
 impl EditableRun {
     pub fn from_run(run: &Run) -> Gd<Self> {
-        Gd::from_object(Self { run: run.clone() })
+        Gd::from_object(Self {
+            editor: Editor::new(run.clone()).expect("timer runs always have at least one segment"),
+            cleaner_rejected: Vec::new(),
+            pending_clean_up: None,
+        })
     }
 
     pub fn get_run(&self) -> Run {
-        return self.run.clone();
+        return self.run().clone();
+    }
+
+    fn run(&self) -> &Run {
+        self.editor.run()
+    }
+
+    // For changes the editor has no method for. The editor is reopened on the changed run afterwards.
+    fn edit_run(&mut self, edit: impl FnOnce(&mut Run)) {
+        let mut run = self.run().clone();
+        edit(&mut run);
+        let method = self.editor.selected_timing_method();
+        if let Ok(editor) = Editor::new(run) {
+            self.editor = editor;
+            self.editor.select_timing_method(method);
+        }
+    }
+
+    // Selects a single segment for the editor's active_segment, using the given timing method
+    fn select(&mut self, idx: i32, rta: bool) -> bool {
+        if idx < 0 || idx as usize >= self.run().len() {
+            return false;
+        }
+        self.editor.select_timing_method(if rta {
+            TimingMethod::RealTime
+        } else {
+            TimingMethod::GameTime
+        });
+        self.editor.select_only(idx as usize);
+        true
+    }
+
+    fn rename_segment(&mut self, idx: usize, name: String) {
+        self.editor.select_only(idx);
+        self.editor.active_segment().set_name(name);
     }
 }
 
@@ -26,74 +84,102 @@ impl EditableRun {
 impl EditableRun {
     #[func]
     fn add_segment(&mut self, name: String) {
-        self.run.push_segment(Segment::new(name));
+        self.editor.select_only(self.run().len() - 1);
+        self.editor.insert_segment_below();
+        self.editor.active_segment().set_name(name);
     }
 
     #[func]
     fn set_game_name(&mut self, name: String) {
-        self.run.set_game_name(name);
+        self.editor.set_game_name(name);
     }
 
     #[func]
     fn get_game_name(&self) -> String {
-        self.run.game_name().to_owned()
+        self.run().game_name().to_owned()
     }
 
     #[func]
     fn get_category_name(&self) -> String {
-        self.run.category_name().to_owned()
+        self.run().category_name().to_owned()
     }
 
     #[func]
     fn set_category_name(&mut self, name: String) {
-        self.run.set_category_name(name);
+        self.editor.set_category_name(name);
     }
 
     #[func]
     fn set_attempt_count(&mut self, c: i32) {
-        self.run.set_attempt_count(c.try_into().unwrap_or_default());
+        self.editor.set_attempt_count(c.try_into().unwrap_or_default());
     }
 
     #[func]
     fn get_attempt_count(&self) -> i32 {
-        self.run.attempt_count() as i32
+        self.run().attempt_count() as i32
     }
 
     #[func]
     fn set_offset(&mut self, offset: f64) {
-        self.run.set_offset(TimeSpan::from_seconds(offset));
+        self.editor.set_offset(TimeSpan::from_seconds(offset));
     }
 
     #[func]
     fn get_offset(&self) -> f64 {
-        self.run.offset().total_seconds()
+        self.run().offset().total_seconds()
+    }
+
+    // Timing method the timer switches to whenever this run is loaded, 0 for real time and 1 for game time.
+    // -1 means the run doesn't have one and the timer keeps whatever it was using.
+    #[func]
+    fn get_default_timing_method(&self) -> i32 {
+        run_settings::timing_method(self.run()).map_or(-1, |m| m as i32)
+    }
+
+    #[func]
+    fn set_default_timing_method(&mut self, method: i32) {
+        let method = run_settings::timing_method_from_int(method);
+        self.edit_run(|run| run_settings::set_timing_method(run, method));
+    }
+
+    // The start delay is the offset the other way around: a delay of 5 means the timer starts at -5
+    // and counts down to 0. The timer has countdown_remaining and countdown_finished for showing it.
+    // Setting a delay replaces any positive offset.
+    #[func]
+    fn set_start_delay(&mut self, seconds: f64) {
+        self.editor.set_offset(TimeSpan::from_seconds(-seconds.max(0.0)));
+    }
+
+    #[func]
+    fn get_start_delay(&self) -> f64 {
+        (-self.run().offset().total_seconds()).max(0.0)
     }
 
     #[func]
     fn generate_comparisons(&mut self) {
-        self.run.regenerate_comparisons();
+        self.edit_run(|run| run.regenerate_comparisons());
     }
 
     // no idea why you would want this
     #[func]
     fn clear_times(&mut self) {
-        self.run.clear_times();
+        self.editor.clear_times();
     }
 
     #[func]
     fn get_auto_splitter_settings(&self) {
-        self.run.auto_splitter_settings();
+        self.run().auto_splitter_settings();
     }
 
     #[func]
     fn set_auto_splitter_settings(&mut self, settings: String) {
-        *self.run.auto_splitter_settings_mut() = settings
+        self.edit_run(|run| *run.auto_splitter_settings_mut() = settings);
     }
 
     #[func]
     fn get_custom_comparisons(&self) -> Array<GString> {
         Array::from_iter(
-            self.run
+            self.run()
                 .custom_comparisons()
                 .iter()
                 .map(|s| GString::from_str(s).expect("literally how does this fail")),
@@ -103,46 +189,194 @@ impl EditableRun {
     #[func]
     fn get_comparisons(&self) -> Array<GString> {
         Array::from_iter(
-            self.run
+            self.run()
                 .comparisons()
                 .map(|s| GString::from_str(s).expect("literally how does this fail")),
         )
     }
 
+    // Comparison management. These return an error message, or an empty string on success.
+    // New names are trimmed and can't be empty, livesplit-core also rejects duplicate names and names
+    // starting with "[Race]".
     #[func]
-    fn add_custom_comparison(&mut self, comp: String) {
-        self.run.custom_comparisons_mut().push(comp);
+    fn add_custom_comparison(&mut self, comp: String) -> String {
+        let comp = match validation::comparison_name(&comp) {
+            Ok(comp) => comp,
+            Err(message) => return message,
+        };
+        match self.editor.add_comparison(comp) {
+            Ok(()) => String::new(),
+            Err(e) => e.to_string(),
+        }
     }
 
+    // idx is into get_custom_comparisons. Personal Best can't be removed.
     #[func]
-    fn remove_custom_comparison(&mut self, idx: i32) {
-        self.run.custom_comparisons_mut().remove(idx as usize);
+    fn remove_custom_comparison(&mut self, idx: i32) -> bool {
+        let Some(comp) = self.run().custom_comparisons().get(idx as usize).cloned() else {
+            return false;
+        };
+        if comp == personal_best::NAME {
+            return false;
+        }
+        self.editor.remove_comparison(&comp);
+        true
+    }
+
+    #[func]
+    fn rename_custom_comparison(&mut self, old_name: String, new_name: String) -> String {
+        if old_name == personal_best::NAME {
+            return String::from("Personal Best can't be renamed");
+        }
+        let new_name = match validation::comparison_name(&new_name) {
+            Ok(new_name) => new_name,
+            Err(message) => return message,
+        };
+        match self.editor.rename_comparison(&old_name, &new_name) {
+            Ok(()) => {
+                self.edit_run(|run| generators::rename_goal(run, &old_name, &new_name));
+                String::new()
+            }
+            Err(e) => e.to_string(),
+        }
+    }
+
+    // Copies any comparison, generated ones included, into a new custom comparison
+    #[func]
+    fn duplicate_comparison(&mut self, name: String, new_name: String) -> String {
+        if !self.run().comparisons().any(|c| c == name) {
+            return format!("There is no comparison named \"{name}\"");
+        }
+        let new_name = match validation::comparison_name(&new_name) {
+            Ok(new_name) => new_name,
+            Err(message) => return message,
+        };
+        match self.editor.copy_comparison(&name, &new_name) {
+            Ok(()) => String::new(),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    // Imports a comparison from another run file as a custom comparison named new_name, e.g. a friend's
+    // or the world record's splits. An empty comparison imports the other run's Personal Best.
+    #[func]
+    fn import_comparison(&mut self, file_path: String, comparison: String, new_name: String) -> String {
+        let new_name = match validation::comparison_name(&new_name) {
+            Ok(new_name) => new_name,
+            Err(message) => return message,
+        };
+        let mut other = match persistence::read_run(Path::new(&file_path)) {
+            Ok((run, _)) => run,
+            Err(result) => return result.message().to_owned(),
+        };
+        // The editor only imports Personal Best, so another comparison is swapped into its place first
+        if !comparison.is_empty() && comparison != personal_best::NAME {
+            other.regenerate_comparisons();
+            if !other.comparisons().any(|c| c == comparison) {
+                return format!("The run has no comparison named \"{comparison}\"");
+            }
+            for segment in other.segments_mut() {
+                *segment.personal_best_split_time_mut() = segment.comparison(&comparison);
+            }
+        }
+        match self.editor.import_comparison(&other, &new_name) {
+            Ok(()) => String::new(),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    // Extra comparison generators for this run, saved with it. See generators.rs for the spec strings,
+    // e.g. ["median_segments", "average_of_last:10", "balanced_target:3600"].
+    #[func]
+    fn get_comparison_generators(&self) -> PackedStringArray {
+        generators::get_specs(self.run())
+            .iter()
+            .map(|s| GString::from(s.to_string().as_str()))
+            .collect()
+    }
+
+    // Returns an error message for the first spec that doesn't parse, nothing is changed then
+    #[func]
+    fn set_comparison_generators(&mut self, specs: PackedStringArray) -> String {
+        let specs: Result<Vec<GeneratorSpec>, String> = specs
+            .as_slice()
+            .iter()
+            .map(|s| GeneratorSpec::parse(&s.to_string()))
+            .collect();
+        match specs {
+            Ok(specs) => {
+                self.edit_run(|run| generators::set_specs(run, &specs));
+                String::new()
+            }
+            Err(message) => message,
+        }
+    }
+
+    // Creates or updates a custom comparison that finishes at the target time ("59:59.99" and so on),
+    // shared out across the segments in proportion to the golds, or the PB segments if use_golds is false.
+    // Other custom comparisons, like imported ones, aren't overwritten.
+    // Returns an error message, or an empty string on success.
+    #[func]
+    fn set_goal_comparison(&mut self, name: String, target: String, use_golds: bool) -> String {
+        let target = match validation::parse_time(&target) {
+            Ok(Some(target)) if target > TimeSpan::zero() => target,
+            Ok(_) => return String::from("The target time can't be empty"),
+            Err(message) => return message,
+        };
+        if !self.run().custom_comparisons().iter().any(|c| *c == name) {
+            if let Err(e) = self.editor.add_comparison(name.as_str()) {
+                return e.to_string();
+            }
+        } else if name == personal_best::NAME {
+            return String::from("Personal Best can't be a goal");
+        } else if generators::get_goal(self.run(), &name).is_none() {
+            return format!("\"{name}\" is already a comparison that isn't a goal");
+        }
+        self.edit_run(|run| generators::write_goal(run, &name, target, use_golds));
+        String::new()
+    }
+
+    // { "target": float, "use_golds": bool }, empty if the comparison isn't a goal
+    #[func]
+    fn get_goal_comparison(&self, name: String) -> Dictionary {
+        let mut dict = Dictionary::new();
+        if let Some((target, use_golds)) = generators::get_goal(self.run(), &name) {
+            dict.set("target", target.total_seconds());
+            dict.set("use_golds", use_golds);
+        }
+        dict
+    }
+
+    // Goals don't follow new golds or PBs on their own, call this to bring them up to date
+    #[func]
+    fn regenerate_goal_comparisons(&mut self) {
+        self.edit_run(generators::regenerate_goals);
     }
 
     #[func]
     fn fix_split(&mut self) {
-        self.run.fix_splits(); // literally what does this do????
+        self.edit_run(|run| run.fix_splits()); // literally what does this do????
     }
 
     // Segments
     #[func]
     fn get_segment_count(&self) -> i32 {
-        self.run.len() as i32
+        self.run().len() as i32
     }
 
     #[func]
     fn get_segment_name(&self, idx: i32) -> String {
-        self.run.segment(idx as usize).name().to_owned()
+        self.run().segment(idx as usize).name().to_owned()
     }
 
     #[func]
     fn set_segment_name(&mut self, idx: i32, name: String) {
-        self.run.segment_mut(idx as usize).set_name(name);
+        self.rename_segment(idx as usize, name);
     }
 
     #[func]
     fn get_segment_comparison(&self, idx: i32, comparing_to: String, rta: bool) -> f64 {
-        let comp = self.run.segment(idx as usize).comparison(&comparing_to);
+        let comp = self.run().segment(idx as usize).comparison(&comparing_to);
         if rta {
             comp.real_time.unwrap_or_default().total_seconds()
         } else {
@@ -152,20 +386,16 @@ impl EditableRun {
 
     #[func]
     fn set_segment_comparison(&mut self, idx: i32, comparing_to: String, rta: bool, time: f64) {
-        let comp = self
-            .run
-            .segment_mut(idx as usize)
-            .comparison_mut(&comparing_to);
-        if rta {
-            comp.real_time = Some(TimeSpan::from_seconds(time));
-        } else {
-            comp.game_time = Some(TimeSpan::from_seconds(time));
+        if self.select(idx, rta) {
+            self.editor
+                .active_segment()
+                .set_comparison_time(&comparing_to, Some(TimeSpan::from_seconds(time)));
         }
     }
 
     #[func]
     fn get_segment_best(&self, idx: i32, rta: bool) -> f64 {
-        let best_time = self.run.segment(idx as usize).best_segment_time();
+        let best_time = self.run().segment(idx as usize).best_segment_time();
         if rta {
             best_time.real_time.unwrap_or_default().total_seconds()
         } else {
@@ -175,34 +405,518 @@ impl EditableRun {
 
     #[func]
     fn set_segment_best(&mut self, idx: i32, rta: bool, time: f64) {
-        let best = self.run.segment_mut(idx as usize).best_segment_time_mut();
-        if rta {
-            best.real_time = Some(TimeSpan::from_seconds(time));
-        } else {
-            best.game_time = Some(TimeSpan::from_seconds(time));
+        if self.select(idx, rta) {
+            self.editor
+                .active_segment()
+                .set_best_segment_time(Some(TimeSpan::from_seconds(time)));
+        }
+    }
+
+    // Time string versions of the setters above, for times typed in by the user ("1:23:45.67", "23:45", "45.6").
+    // An empty string clears the time. Split times going backwards and golds slower than the PB segment are
+    // refused, see validation.rs. Returns an error message, or an empty string on success.
+    #[func]
+    fn set_segment_comparison_text(&mut self, idx: i32, comparing_to: String, rta: bool, text: String) -> String {
+        let time = match validation::parse_time(&text) {
+            Ok(time) => time,
+            Err(message) => return message,
+        };
+        if !self.select(idx, rta) {
+            return format!("There is no segment {idx}");
+        }
+        let method = self.editor.selected_timing_method();
+        if let Err(message) = validation::check_split_time(self.run(), idx as usize, &comparing_to, method, time) {
+            return message;
+        }
+        self.editor.active_segment().set_comparison_time(&comparing_to, time);
+        String::new()
+    }
+
+    #[func]
+    fn set_segment_best_text(&mut self, idx: i32, rta: bool, text: String) -> String {
+        let time = match validation::parse_time(&text) {
+            Ok(time) => time,
+            Err(message) => return message,
+        };
+        if !self.select(idx, rta) {
+            return format!("There is no segment {idx}");
+        }
+        let method = self.editor.selected_timing_method();
+        if let Err(message) = validation::check_best_segment_time(self.run(), idx as usize, method, time) {
+            return message;
+        }
+        self.editor.active_segment().set_best_segment_time(time);
+        String::new()
+    }
+
+    #[func]
+    fn clear_segment_comparison(&mut self, idx: i32, comparing_to: String, rta: bool) {
+        if self.select(idx, rta) {
+            self.editor.active_segment().set_comparison_time(&comparing_to, None);
+        }
+    }
+
+    #[func]
+    fn clear_segment_best(&mut self, idx: i32, rta: bool) {
+        if self.select(idx, rta) {
+            self.editor.active_segment().set_best_segment_time(None);
+        }
+    }
+
+    // The editor merges the removed segment's history into the next one.
+    // Fails if it's the only segment, a run always needs at least one.
+    #[func]
+    fn remove_segment(&mut self, idx: i32) -> bool {
+        if !self.select(idx, true) || !self.editor.can_remove_segments() {
+            return false;
+        }
+        self.editor.remove_segments();
+        true
+    }
+
+    // For route changes. Unlike removing a segment, these keep the history of both, see segments.rs for how.
+    // An empty name keeps the second segment's name.
+    #[func]
+    fn merge_segments(&mut self, idx: i32, name: String) -> bool {
+        if idx < 0 || idx as usize + 1 >= self.run().len() {
+            return false;
         }
+        self.edit_run(|run| {
+            segments::merge(run, idx as usize, &name);
+        });
+        true
     }
 
+    // Inserts a segment named name before idx, taking ratio (0 to 1) of its times. A ratio of 0 leaves the new segment empty.
     #[func]
-    fn remove_segment(&mut self, idx: i32) {
-        self.run.segments_mut().remove(idx as usize);
+    fn split_segment(&mut self, idx: i32, name: String, ratio: f64) -> bool {
+        if idx < 0 || idx as usize >= self.run().len() || !(0.0..1.0).contains(&ratio) {
+            return false;
+        }
+        self.edit_run(|run| {
+            segments::split(run, idx as usize, &name, ratio);
+        });
+        true
     }
 
     #[func]
     fn move_up_segment(&mut self, idx: i32) {
-        let segments = self.run.segments_mut();
-        let idx_u = idx as usize;
-        if idx_u > 0 {
-            segments.swap(idx_u, idx_u - 1);
+        if self.select(idx, true) && self.editor.can_move_segments_up() {
+            self.editor.move_segments_up();
         }
     }
 
     #[func]
     fn move_down_segment(&mut self, idx: i32) {
-        let segments = self.run.segments_mut();
-        let idx_u = idx as usize;
-        if idx_u < segments.len() - 1 {
-            segments.swap(idx_u, idx_u + 1);
+        if self.select(idx, true) && self.editor.can_move_segments_down() {
+            self.editor.move_segments_down();
         }
     }
+
+    // Exports for external analysis, see "Exporting Run Data" in the README for the format
+    #[func]
+    fn to_json(&self) -> String {
+        export::run_to_json(self.run())
+    }
+
+    #[func]
+    fn export_json(&self, file_path: String) -> bool {
+        std::fs::write(file_path, export::run_to_json(self.run())).is_ok()
+    }
+
+    // Writes three files next to each other: base_path.segments.csv, base_path.attempts.csv and base_path.history.csv
+    #[func]
+    fn export_csv(&self, base_path: String) -> bool {
+        export::write_run_csv(self.run(), Path::new(&base_path)).is_ok()
+    }
+
+    // Attempt history. Attempts and history entries are dictionaries laid out like the JSON export
+    #[func]
+    fn get_attempts(&self) -> Array<Dictionary> {
+        Array::from_iter(self.run().attempt_history().iter().map(export::attempt_dict))
+    }
+
+    // Every segment time recorded for this segment, each with its attempt_id
+    #[func]
+    fn get_segment_history(&self, idx: i32) -> Array<Dictionary> {
+        Array::from_iter(
+            self.run()
+                .segment(idx as usize)
+                .segment_history()
+                .iter()
+                .map(|&(attempt_id, time)| {
+                    let mut dict = export::time_dict(time);
+                    dict.set("attempt_id", attempt_id);
+                    dict
+                }),
+        )
+    }
+
+    // Empty if the attempt didn't reach this segment
+    #[func]
+    fn get_segment_history_time(&self, idx: i32, attempt_id: i32) -> Dictionary {
+        self.run()
+            .segment(idx as usize)
+            .segment_history()
+            .get(attempt_id)
+            .map(export::time_dict)
+            .unwrap_or_default()
+    }
+
+    // Removes the attempt along with its segment times. Golds it set are worked out again from the remaining history.
+    #[func]
+    fn delete_attempt(&mut self, attempt_id: i32) -> bool {
+        if !self.run().attempt_history().iter().any(|a| a.index() == attempt_id) {
+            return false;
+        }
+
+        self.edit_run(|run| {
+            // Golds the attempt set go back to the best of what's left, or None if nothing else reached the segment
+            let golds_set: Vec<[bool; 2]> = run
+                .segments()
+                .iter()
+                .map(|s| {
+                    let time = s.segment_history().get(attempt_id).unwrap_or_default();
+                    TimingMethod::all()
+                        .map(|method| time[method].is_some() && time[method] == s.best_segment_time()[method])
+                })
+                .collect();
+            segments::rebuild_attempt_history(run, |attempt| (attempt.index() != attempt_id).then(|| attempt.clone()));
+            for (idx, golds_set) in golds_set.into_iter().enumerate() {
+                for (method, gold_set) in TimingMethod::all().into_iter().zip(golds_set) {
+                    if gold_set {
+                        let best = segments::best_from_history(run, idx, method);
+                        run.segment_mut(idx).best_segment_time_mut()[method] = best;
+                    }
+                }
+            }
+            run.regenerate_comparisons();
+        });
+        true
+    }
+
+    // Sum of best cleaning. Finds segment times from the history that beat the combined golds of the segments
+    // they span, usually from a missed split or a timer glitch, and lets the user remove them one at a time.
+    // Call start_sum_of_best_cleaning, then next_sum_of_best_clean_up and accept or reject until it returns "".
+    #[func]
+    fn start_sum_of_best_cleaning(&mut self) {
+        self.cleaner_rejected.clear();
+        self.pending_clean_up = None;
+    }
+
+    // Describes the next candidate: the segments, the times involved and when the attempt was.
+    // Empty once there are no candidates left.
+    #[func]
+    fn next_sum_of_best_clean_up(&mut self) -> String {
+        let run = self.run().clone();
+        let mut cleaner = self.editor.clean_sum_of_best();
+        self.pending_clean_up = None;
+        while let Some(clean_up) = cleaner.next_potential_clean_up() {
+            let message = clean_up.to_string();
+            let Some(key) = clean_up_key(&run, clean_up.into()) else {
+                continue;
+            };
+            if !self.cleaner_rejected.contains(&key) {
+                self.pending_clean_up = Some(key);
+                return message;
+            }
+        }
+        String::new()
+    }
+
+    // Removes the candidate's segment time from the history
+    #[func]
+    fn accept_sum_of_best_clean_up(&mut self) -> bool {
+        let Some((idx, attempt_id)) = self.pending_clean_up.take() else {
+            return false;
+        };
+        self.edit_run(|run| {
+            run.segment_mut(idx).segment_history_mut().remove(attempt_id);
+        });
+        true
+    }
+
+    #[func]
+    fn reject_sum_of_best_clean_up(&mut self) -> bool {
+        let Some(key) = self.pending_clean_up.take() else {
+            return false;
+        };
+        self.cleaner_rejected.push(key);
+        true
+    }
+
+    // Comparing and merging copies of a run, see diff.rs. Diffs are { "error": String, "differences": Array },
+    // error being empty unless a file couldn't be read.
+    #[func]
+    fn diff_with_file(&self, file_path: String) -> Dictionary {
+        match persistence::read_run(Path::new(&file_path)) {
+            Ok((theirs, _)) => diff_dict(self.run(), &theirs, ""),
+            Err(result) => diff_dict(self.run(), self.run(), result.message()),
+        }
+    }
+
+    #[func]
+    fn diff_run_files(file_path_a: String, file_path_b: String) -> Dictionary {
+        let read = |path: &String| persistence::read_run(Path::new(path)).map(|(run, _)| run);
+        match (read(&file_path_a), read(&file_path_b)) {
+            (Ok(ours), Ok(theirs)) => diff_dict(&ours, &theirs, ""),
+            (Err(result), _) | (_, Err(result)) => {
+                let mut dict = Dictionary::new();
+                dict.set("error", result.message());
+                dict.set("differences", Array::<Dictionary>::new());
+                dict
+            }
+        }
+    }
+
+    // Adds the attempts from the other file this run doesn't have yet.
+    // Returns an error message, or an empty string on success.
+    #[func]
+    fn merge_history_from_file(&mut self, file_path: String) -> String {
+        let theirs = match persistence::read_run(Path::new(&file_path)) {
+            Ok((run, _)) => run,
+            Err(result) => return result.message().to_owned(),
+        };
+        let mut run = self.run().clone();
+        if let Err(message) = diff::merge_history(&mut run, &theirs) {
+            return message;
+        }
+        self.edit_run(|r| *r = run);
+        String::new()
+    }
+
+    // Subsplits, see subsplits.rs for the naming convention
+    // Inserting inside a group keeps the new segment in the group. False if idx isn't a segment.
+    #[func]
+    fn insert_segment_above(&mut self, idx: i32, name: String) -> bool {
+        if !self.select(idx, true) {
+            return false;
+        }
+        let name = if subsplits::in_group(self.run().segment(idx as usize).name()) {
+            subsplits::group_member_name(&name)
+        } else {
+            name
+        };
+        self.editor.insert_segment_above();
+        self.editor.active_segment().set_name(name);
+        true
+    }
+
+    #[func]
+    fn insert_segment_below(&mut self, idx: i32, name: String) -> bool {
+        if !self.select(idx, true) {
+            return false;
+        }
+        let name = if subsplits::is_subsplit(self.run().segment(idx as usize).name()) {
+            subsplits::group_member_name(&name)
+        } else {
+            name
+        };
+        self.editor.insert_segment_below();
+        self.editor.active_segment().set_name(name);
+        true
+    }
+
+    // Segment name without the subsplit markers
+    #[func]
+    fn get_segment_display_name(&self, idx: i32) -> String {
+        subsplits::base_name(self.run().segment(idx as usize).name()).to_owned()
+    }
+
+    // One entry per split or group, in order: { "group": String, "is_group": bool, "children": PackedInt32Array }
+    // A plain split has is_group false and its own index as the only child.
+    #[func]
+    fn get_subsplit_tree(&self) -> Array<Dictionary> {
+        let tree = subsplits::parse_tree(self.run().segments().iter().map(|s| s.name()));
+        Array::from_iter(tree.into_iter().map(|node| {
+            let mut dict = Dictionary::new();
+            match node {
+                SplitNode::Single(idx) => {
+                    dict.set("group", "");
+                    dict.set("is_group", false);
+                    dict.set("children", PackedInt32Array::from(&[idx as i32][..]));
+                }
+                SplitNode::Group { name, indices } => {
+                    let children: Vec<i32> = indices.into_iter().map(|i| i as i32).collect();
+                    dict.set("group", name.as_str());
+                    dict.set("is_group", true);
+                    dict.set("children", PackedInt32Array::from(children.as_slice()));
+                }
+            }
+            dict
+        }))
+    }
+
+    // Groups segments first..=last. Fails if the range cuts into an existing group from above,
+    // since the group's "-" segments would end up in this one.
+    #[func]
+    fn create_group(&mut self, first: i32, last: i32, group_name: String) -> bool {
+        let (first, last) = (first as usize, last as usize);
+        if first > last || last >= self.run().len() {
+            return false;
+        }
+        if first > 0 && subsplits::is_subsplit(self.run().segment(first - 1).name()) {
+            return false;
+        }
+        for idx in first..=last {
+            let base = subsplits::base_name(self.run().segment(idx).name()).to_owned();
+            let name = if idx == last {
+                subsplits::group_end_name(&group_name, &base)
+            } else {
+                subsplits::group_member_name(&base)
+            };
+            self.rename_segment(idx, name);
+        }
+        true
+    }
+
+    // Turns the group containing idx back into plain splits
+    #[func]
+    fn collapse_group(&mut self, idx: i32) -> bool {
+        let Some((first, last)) = subsplits::group_range(self.run().segments().iter().map(|s| s.name()), idx as usize)
+        else {
+            return false;
+        };
+        for i in first..=last {
+            let base = subsplits::base_name(self.run().segment(i).name()).to_owned();
+            self.rename_segment(i, base);
+        }
+        true
+    }
+
+    #[func]
+    fn rename_group(&mut self, idx: i32, group_name: String) -> bool {
+        let Some((_, last)) = subsplits::group_range(self.run().segments().iter().map(|s| s.name()), idx as usize)
+        else {
+            return false;
+        };
+        let base = subsplits::base_name(self.run().segment(last).name()).to_owned();
+        self.rename_segment(last, subsplits::group_end_name(&group_name, &base));
+        true
+    }
+
+    // Icons, as the image file's bytes. Setting an empty array removes the icon.
+    #[func]
+    fn get_game_icon(&self) -> PackedByteArray {
+        PackedByteArray::from(self.run().game_icon().data())
+    }
+
+    #[func]
+    fn set_game_icon(&mut self, data: PackedByteArray) {
+        if data.is_empty() {
+            self.editor.remove_game_icon();
+        } else {
+            self.editor.set_game_icon(Image::new(data.as_slice().into()));
+        }
+    }
+
+    #[func]
+    fn get_segment_icon(&self, idx: i32) -> PackedByteArray {
+        PackedByteArray::from(self.run().segment(idx as usize).icon().data())
+    }
+
+    #[func]
+    fn set_segment_icon(&mut self, idx: i32, data: PackedByteArray) {
+        if !self.select(idx, true) {
+            return;
+        }
+        if data.is_empty() {
+            self.editor.active_segment().remove_icon();
+        } else {
+            self.editor
+                .active_segment()
+                .set_icon(Image::new(data.as_slice().into()));
+        }
+    }
+
+    // null if there's no icon or godot can't read it
+    #[func]
+    fn get_game_icon_texture(&self) -> Option<Gd<ImageTexture>> {
+        icons::texture_from_bytes(self.run().game_icon().data())
+    }
+
+    #[func]
+    fn get_segment_icon_texture(&self, idx: i32) -> Option<Gd<ImageTexture>> {
+        icons::texture_from_bytes(self.run().segment(idx as usize).icon().data())
+    }
+
+    // Run metadata, see export::metadata_dict for the dictionary's keys
+    #[func]
+    fn get_metadata(&self) -> Dictionary {
+        export::metadata_dict(self.run().metadata())
+    }
+
+    #[func]
+    fn set_run_id(&mut self, id: String) {
+        self.editor.set_run_id(id);
+    }
+
+    #[func]
+    fn set_platform(&mut self, platform: String) {
+        self.editor.set_platform_name(platform);
+    }
+
+    #[func]
+    fn set_region(&mut self, region: String) {
+        self.editor.set_region_name(region);
+    }
+
+    #[func]
+    fn set_uses_emulator(&mut self, uses_emulator: bool) {
+        self.editor.set_emulator_usage(uses_emulator);
+    }
+
+    // speedrun.com category variables, e.g. "Glitches" = "No Major Glitches"
+    #[func]
+    fn set_speedrun_com_variable(&mut self, name: String, value: String) {
+        self.editor.set_speedrun_com_variable(name, value);
+    }
+
+    #[func]
+    fn remove_speedrun_com_variable(&mut self, name: String) {
+        self.editor.remove_speedrun_com_variable(&name);
+    }
+
+    // Custom variables set here are saved with the run, unlike ones set by an autosplitter during a run
+    #[func]
+    fn set_custom_variable(&mut self, name: String, value: String) {
+        self.editor.add_custom_variable(&name);
+        self.editor.set_custom_variable(&name, value);
+    }
+
+    #[func]
+    fn remove_custom_variable(&mut self, name: String) {
+        self.editor.remove_custom_variable(&name);
+    }
+}
+
+fn diff_dict(ours: &Run, theirs: &Run, error: &str) -> Dictionary {
+    let differences = if error.is_empty() {
+        Array::from_iter(diff::diff(ours, theirs).iter().map(|d| d.to_dict()))
+    } else {
+        Array::new()
+    };
+    let mut dict = Dictionary::new();
+    dict.set("error", error);
+    dict.set("differences", differences);
+    dict
+}
+
+// The (segment, attempt id) of the history entry a clean up removes. CleanUp doesn't say, and accepting or
+// rejecting others moves the cleaner's candidates around, so it's applied to a copy of the run to find out.
+fn clean_up_key(run: &Run, clean_up: CleanUp) -> Option<(usize, i32)> {
+    let mut editor = Editor::new(run.clone()).ok()?;
+    editor.clean_sum_of_best().apply(clean_up);
+    let cleaned = editor.close();
+    run.segments()
+        .iter()
+        .zip(cleaned.segments())
+        .enumerate()
+        .find_map(|(idx, (before, after))| {
+            before
+                .segment_history()
+                .iter()
+                .find(|&&(attempt_id, _)| after.segment_history().get(attempt_id).is_none())
+                .map(|&(attempt_id, _)| (idx, attempt_id))
+        })
 }
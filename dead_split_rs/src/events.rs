@@ -0,0 +1,91 @@
+use std::sync::{Arc, Mutex};
+
+use livesplit_core::{Timer, TimerPhase};
+
+use crate::timer_write;
+
+// Things that happened to the timer, turned into signals by DeadSplitTimer::process.
+// The autosplitter thread can't emit signals itself, so everything goes through this queue.
+pub enum TimerEvent {
+    Started,
+    Split(i32),
+    SplitUndone,
+    SplitSkipped,
+    Paused,
+    Resumed,
+    Reset(bool),
+    RunFinished(bool),
+}
+
+#[derive(Clone, Default)]
+pub struct EventQueue(Arc<Mutex<Vec<TimerEvent>>>);
+
+impl EventQueue {
+    pub fn push(&self, event: TimerEvent) {
+        self.0.lock().unwrap().push(event);
+    }
+
+    pub fn drain(&self) -> Vec<TimerEvent> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+// Runs an action on the timer and queues whatever events it caused, found by comparing the timer before and after.
+// reset_saved is only used if the action resets the timer.
+pub fn act<R>(
+    timer: &livesplit_core::SharedTimer,
+    events: &EventQueue,
+    reset_saved: bool,
+    action: impl FnOnce(&mut Timer) -> R,
+) -> R {
+    let mut binding = timer_write(timer);
+    let phase = binding.current_phase();
+    let split_index = binding.current_split_index();
+    // Personal best before the action, to tell whether a finished run beat it
+    let pb = binding
+        .run()
+        .segments()
+        .last()
+        .and_then(|s| s.personal_best_split_time()[binding.current_timing_method()]);
+
+    let result = action(&mut *binding);
+
+    let new_phase = binding.current_phase();
+    let new_split_index = binding.current_split_index();
+    match (phase, new_phase) {
+        (TimerPhase::NotRunning, TimerPhase::NotRunning) => {}
+        (TimerPhase::NotRunning, _) => events.push(TimerEvent::Started),
+        (_, TimerPhase::NotRunning) => events.push(TimerEvent::Reset(reset_saved)),
+        (TimerPhase::Running, TimerPhase::Paused) => events.push(TimerEvent::Paused),
+        (TimerPhase::Paused, TimerPhase::Running) => events.push(TimerEvent::Resumed),
+        _ => {}
+    }
+
+    if phase != TimerPhase::NotRunning && new_phase != TimerPhase::NotRunning {
+        if let (Some(old), Some(new)) = (split_index, new_split_index) {
+            if new < old {
+                events.push(TimerEvent::SplitUndone);
+            } else if new > old {
+                // Split times are kept for the segment that was just finished, skipped splits don't have one
+                let method = binding.current_timing_method();
+                if binding.run().segment(old).split_time()[method].is_some() {
+                    events.push(TimerEvent::Split(old as i32));
+                } else {
+                    events.push(TimerEvent::SplitSkipped);
+                }
+            }
+        }
+
+        if phase != TimerPhase::Ended && new_phase == TimerPhase::Ended {
+            let final_time = binding.snapshot().current_time()[binding.current_timing_method()];
+            let is_pb = match (final_time, pb) {
+                (Some(t), Some(pb)) => t < pb,
+                (Some(_), None) => true,
+                _ => false,
+            };
+            events.push(TimerEvent::RunFinished(is_pb));
+        }
+    }
+
+    result
+}
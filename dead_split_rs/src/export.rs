@@ -0,0 +1,196 @@
+use std::{fs, io, path::Path};
+
+use godot::{classes::Json, prelude::*};
+use livesplit_core::{
+    run::{Attempt, RunMetadata},
+    AtomicDateTime, Run, Time, TimeSpan,
+};
+
+use crate::run_settings;
+
+// Run data exports for spreadsheets and scripts. The layout of both formats is described under
+// "Exporting Run Data" in the README, bump SCHEMA_VERSION whenever it changes.
+pub const SCHEMA_VERSION: i64 = 1;
+
+fn seconds_or_nil(t: Option<TimeSpan>) -> Variant {
+    match t {
+        Some(t) => Variant::from(t.total_seconds()),
+        None => Variant::nil(),
+    }
+}
+
+fn unix_or_nil(t: Option<AtomicDateTime>) -> Variant {
+    match t {
+        Some(t) => Variant::from(t.time.unix_timestamp()),
+        None => Variant::nil(),
+    }
+}
+
+pub fn time_dict(t: Time) -> Dictionary {
+    let mut dict = Dictionary::new();
+    dict.set("real_time", seconds_or_nil(t.real_time));
+    dict.set("game_time", seconds_or_nil(t.game_time));
+    dict
+}
+
+pub fn attempt_dict(attempt: &Attempt) -> Dictionary {
+    let mut dict = time_dict(attempt.time());
+    dict.set("id", attempt.index());
+    dict.set("started", unix_or_nil(attempt.started()));
+    dict.set("ended", unix_or_nil(attempt.ended()));
+    dict.set("pause_time", seconds_or_nil(attempt.pause_time()));
+    dict
+}
+
+// Also used for showing metadata in godot, not part of the exports yet
+pub fn metadata_dict(metadata: &RunMetadata) -> Dictionary {
+    let mut speedrun_com_variables = Dictionary::new();
+    for (name, value) in metadata.speedrun_com_variables() {
+        speedrun_com_variables.set(name.to_string(), value.to_string());
+    }
+    let mut custom_variables = Dictionary::new();
+    for (name, variable) in metadata.custom_variables() {
+        if run_settings::is_setting(name) {
+            continue;
+        }
+        custom_variables.set(name.to_string(), variable.value.as_str());
+    }
+
+    let mut dict = Dictionary::new();
+    dict.set("run_id", metadata.run_id());
+    dict.set("platform", metadata.platform_name());
+    dict.set("region", metadata.region_name());
+    dict.set("uses_emulator", metadata.uses_emulator());
+    dict.set("speedrun_com_variables", speedrun_com_variables);
+    dict.set("custom_variables", custom_variables);
+    dict
+}
+
+pub fn run_to_json(run: &Run) -> String {
+    let mut attempts = VariantArray::new();
+    for attempt in run.attempt_history() {
+        attempts.push(&attempt_dict(attempt).to_variant());
+    }
+
+    let mut segments = VariantArray::new();
+    for (idx, segment) in run.segments().iter().enumerate() {
+        let mut comparisons = Dictionary::new();
+        for comparison in run.comparisons() {
+            comparisons.set(comparison, time_dict(segment.comparison(comparison)));
+        }
+
+        let mut history = VariantArray::new();
+        for &(attempt_id, time) in segment.segment_history().iter() {
+            let mut dict = time_dict(time);
+            dict.set("attempt_id", attempt_id);
+            history.push(&dict.to_variant());
+        }
+
+        let mut dict = Dictionary::new();
+        dict.set("index", idx as i64);
+        dict.set("name", segment.name());
+        dict.set("best_segment", time_dict(segment.best_segment_time()));
+        dict.set("comparisons", comparisons);
+        dict.set("history", history);
+        segments.push(&dict.to_variant());
+    }
+
+    let mut root = Dictionary::new();
+    root.set("schema_version", SCHEMA_VERSION);
+    root.set("game", run.game_name());
+    root.set("category", run.category_name());
+    root.set("attempt_count", run.attempt_count() as i64);
+    root.set("offset", run.offset().total_seconds());
+    root.set("segments", segments);
+    root.set("attempts", attempts);
+
+    Json::stringify_ex(&root.to_variant())
+        .indent("\t")
+        .sort_keys(false)
+        .full_precision(true)
+        .done()
+        .to_string()
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+fn csv_time(t: Option<TimeSpan>) -> String {
+    t.map(|t| t.total_seconds().to_string()).unwrap_or_default()
+}
+
+fn csv_date(t: Option<AtomicDateTime>) -> String {
+    t.map(|t| t.time.unix_timestamp().to_string()).unwrap_or_default()
+}
+
+// Writes base.segments.csv, base.attempts.csv and base.history.csv
+pub fn write_run_csv(run: &Run, base_path: &Path) -> io::Result<()> {
+    let with_suffix = |suffix: &str| {
+        let mut name = base_path.as_os_str().to_owned();
+        name.push(suffix);
+        name
+    };
+
+    let comparisons: Vec<&str> = run.comparisons().collect();
+    let mut segments = String::from("index,name,best_real_time,best_game_time");
+    for comparison in &comparisons {
+        segments.push_str(&format!(
+            ",{},{}",
+            csv_field(&format!("{comparison} real_time")),
+            csv_field(&format!("{comparison} game_time"))
+        ));
+    }
+    segments.push('\n');
+    for (idx, segment) in run.segments().iter().enumerate() {
+        let best = segment.best_segment_time();
+        segments.push_str(&format!(
+            "{idx},{},{},{}",
+            csv_field(segment.name()),
+            csv_time(best.real_time),
+            csv_time(best.game_time)
+        ));
+        for comparison in &comparisons {
+            let time = segment.comparison(comparison);
+            segments.push_str(&format!(
+                ",{},{}",
+                csv_time(time.real_time),
+                csv_time(time.game_time)
+            ));
+        }
+        segments.push('\n');
+    }
+    fs::write(with_suffix(".segments.csv"), segments)?;
+
+    let mut attempts = String::from("id,started,ended,real_time,game_time,pause_time\n");
+    for attempt in run.attempt_history() {
+        let time = attempt.time();
+        attempts.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            attempt.index(),
+            csv_date(attempt.started()),
+            csv_date(attempt.ended()),
+            csv_time(time.real_time),
+            csv_time(time.game_time),
+            csv_time(attempt.pause_time())
+        ));
+    }
+    fs::write(with_suffix(".attempts.csv"), attempts)?;
+
+    let mut history = String::from("attempt_id,segment_index,segment_name,real_time,game_time\n");
+    for (idx, segment) in run.segments().iter().enumerate() {
+        let name = csv_field(segment.name());
+        for &(attempt_id, time) in segment.segment_history().iter() {
+            history.push_str(&format!(
+                "{attempt_id},{idx},{name},{},{}\n",
+                csv_time(time.real_time),
+                csv_time(time.game_time)
+            ));
+        }
+    }
+    fs::write(with_suffix(".history.csv"), history)
+}
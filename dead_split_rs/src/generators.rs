@@ -0,0 +1,256 @@
+use std::fmt;
+
+use livesplit_core::{
+    comparison::{self, latest_run, median_segments, ComparisonGenerator},
+    run::Attempt,
+    Run, Segment, TimeSpan, TimingMethod,
+};
+
+use crate::run_settings;
+
+// Comparison generators a run can turn on or off, stored in the run's settings as a ";" separated list of:
+//   median_segments             livesplit-core's Median Segments
+//   latest_run                  livesplit-core's Latest Run
+//   average_of_last:<count>     average of each segment over the last <count> attempts
+//   balanced_target:<seconds>   golds scaled up to finish at the target time
+// Median Segments and Latest Run are on for runs that have never changed this, like in livesplit.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GeneratorSpec {
+    MedianSegments,
+    LatestRun,
+    AverageOfLast(usize),
+    BalancedTarget(TimeSpan),
+}
+
+impl GeneratorSpec {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (kind, arg) = match spec.trim().split_once(':') {
+            Some((kind, arg)) => (kind, Some(arg.trim())),
+            None => (spec.trim(), None),
+        };
+        match (kind, arg) {
+            ("median_segments", None) => Ok(Self::MedianSegments),
+            ("latest_run", None) => Ok(Self::LatestRun),
+            ("average_of_last", Some(arg)) => match arg.parse::<usize>() {
+                Ok(count) if count > 0 => Ok(Self::AverageOfLast(count)),
+                _ => Err(format!("\"{arg}\" is not a valid attempt count")),
+            },
+            ("balanced_target", Some(arg)) => match arg.parse::<f64>() {
+                Ok(seconds) if seconds > 0.0 => Ok(Self::BalancedTarget(TimeSpan::from_seconds(seconds))),
+                _ => Err(format!("\"{arg}\" is not a valid target time")),
+            },
+            _ => Err(format!("\"{spec}\" is not a comparison generator")),
+        }
+    }
+
+    fn build(&self) -> Box<dyn ComparisonGenerator> {
+        match self {
+            Self::MedianSegments => Box::new(median_segments::MedianSegments),
+            Self::LatestRun => Box::new(latest_run::LatestRun),
+            Self::AverageOfLast(count) => Box::new(AverageOfLast {
+                name: format!("Average of Last {count}"),
+                count: *count,
+            }),
+            Self::BalancedTarget(target) => Box::new(BalancedTarget {
+                name: format!("Balanced {}", format_target(*target)),
+                target: *target,
+            }),
+        }
+    }
+}
+
+impl fmt::Display for GeneratorSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MedianSegments => write!(f, "median_segments"),
+            Self::LatestRun => write!(f, "latest_run"),
+            Self::AverageOfLast(count) => write!(f, "average_of_last:{count}"),
+            Self::BalancedTarget(target) => write!(f, "balanced_target:{}", target.total_seconds()),
+        }
+    }
+}
+
+// 3600 -> "1:00:00", 754.5 -> "12:34"
+fn format_target(t: TimeSpan) -> String {
+    let total = t.total_seconds() as i64;
+    let (hours, minutes, seconds) = (total / 3600, total / 60 % 60, total % 60);
+    if hours > 0 {
+        format!("{hours}:{minutes:02}:{seconds:02}")
+    } else {
+        format!("{minutes}:{seconds:02}")
+    }
+}
+
+pub fn get_specs(run: &Run) -> Vec<GeneratorSpec> {
+    match run_settings::get(run, run_settings::COMPARISON_GENERATORS) {
+        Some(value) => value
+            .split(';')
+            .filter_map(|spec| GeneratorSpec::parse(spec).ok())
+            .collect(),
+        None => vec![GeneratorSpec::MedianSegments, GeneratorSpec::LatestRun],
+    }
+}
+
+pub fn set_specs(run: &mut Run, specs: &[GeneratorSpec]) {
+    // "none" rather than an empty value, which would put the defaults back
+    let value = if specs.is_empty() {
+        String::from("none")
+    } else {
+        specs.iter().map(|s| s.to_string()).collect::<Vec<_>>().join(";")
+    };
+    run_settings::set(run, run_settings::COMPARISON_GENERATORS, value);
+    apply(run);
+}
+
+// Sets up the run's generators from its settings. Runs fresh from a file only have livesplit's defaults,
+// so this has to be called on every run that gets loaded.
+pub fn apply(run: &mut Run) {
+    let specs = get_specs(run);
+    let generators = run.comparison_generators_mut();
+    *generators = comparison::default_generators();
+    generators.retain(|g| g.name() != median_segments::NAME && g.name() != latest_run::NAME);
+    generators.extend(specs.iter().map(|s| s.build()));
+    run.regenerate_comparisons();
+}
+
+// Splits a target time across the segments in proportion to the weights, e.g. golds or PB segments.
+// All None if any weight is missing, there's no fair way to share out the time then.
+pub fn distribute(target: TimeSpan, weights: &[Option<TimeSpan>]) -> Vec<Option<TimeSpan>> {
+    let total: Option<f64> = weights.iter().map(|w| w.map(|w| w.total_seconds())).sum();
+    let Some(total) = total.filter(|&t| t > 0.0) else {
+        return vec![None; weights.len()];
+    };
+    let mut elapsed = 0.0;
+    weights
+        .iter()
+        .map(|w| {
+            elapsed += w.unwrap_or_default().total_seconds();
+            Some(TimeSpan::from_seconds(target.total_seconds() * elapsed / total))
+        })
+        .collect()
+}
+
+// Goal comparisons are custom comparisons filled in from a target time, like Balanced Target but kept
+// as a plain comparison, so they only change when regenerated. Stored in the run's settings as
+// "golds:<seconds>" or "pb:<seconds>", for which segment times the target is shared out by.
+fn pb_segments(run: &Run, method: TimingMethod) -> Vec<Option<TimeSpan>> {
+    let mut previous = Some(TimeSpan::zero());
+    run.segments()
+        .iter()
+        .map(|s| {
+            let split_time = s.personal_best_split_time()[method];
+            let segment_time = previous.zip(split_time).map(|(start, end)| end - start);
+            previous = split_time;
+            segment_time
+        })
+        .collect()
+}
+
+pub fn write_goal(run: &mut Run, name: &str, target: TimeSpan, use_golds: bool) {
+    for method in TimingMethod::all() {
+        let weights: Vec<Option<TimeSpan>> = if use_golds {
+            run.segments().iter().map(|s| s.best_segment_time()[method]).collect()
+        } else {
+            pb_segments(run, method)
+        };
+        for (segment, time) in run.segments_mut().iter_mut().zip(distribute(target, &weights)) {
+            segment.comparison_mut(name)[method] = time;
+        }
+    }
+    let basis = if use_golds { "golds" } else { "pb" };
+    run_settings::set(
+        run,
+        &format!("{}{name}", run_settings::GOAL),
+        format!("{basis}:{}", target.total_seconds()),
+    );
+}
+
+// (target, use_golds) for a goal comparison
+pub fn get_goal(run: &Run, name: &str) -> Option<(TimeSpan, bool)> {
+    let value = run_settings::get(run, &format!("{}{name}", run_settings::GOAL))?;
+    let (basis, seconds) = value.split_once(':')?;
+    Some((TimeSpan::from_seconds(seconds.parse().ok()?), basis == "golds"))
+}
+
+// Goals are stored under their comparison's name, so the setting has to follow the comparison when it's renamed
+pub fn rename_goal(run: &mut Run, old_name: &str, new_name: &str) {
+    let old_key = format!("{}{old_name}", run_settings::GOAL);
+    let Some(value) = run_settings::get(run, &old_key).map(str::to_owned) else {
+        return;
+    };
+    run_settings::set(run, &old_key, String::new());
+    run_settings::set(run, &format!("{}{new_name}", run_settings::GOAL), value);
+}
+
+// Refills every goal comparison from the current golds or PB. Goals whose comparison was removed are dropped.
+pub fn regenerate_goals(run: &mut Run) {
+    for (name, _) in run_settings::with_prefix(run, run_settings::GOAL) {
+        if !run.custom_comparisons().iter().any(|c| *c == name) {
+            run_settings::set(run, &format!("{}{name}", run_settings::GOAL), String::new());
+            continue;
+        }
+        if let Some((target, use_golds)) = get_goal(run, &name) {
+            write_goal(run, &name, target, use_golds);
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct AverageOfLast {
+    name: String,
+    count: usize,
+}
+
+impl ComparisonGenerator for AverageOfLast {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    // Segments none of the attempts reached fall back to the gold
+    fn generate(&mut self, segments: &mut [Segment], attempts: &[Attempt]) {
+        let ids: Vec<i32> = attempts[attempts.len().saturating_sub(self.count)..]
+            .iter()
+            .map(|a| a.index())
+            .collect();
+
+        for method in TimingMethod::all() {
+            let mut total = Some(TimeSpan::zero());
+            for segment in segments.iter_mut() {
+                let times: Vec<f64> = ids
+                    .iter()
+                    .filter_map(|&id| segment.segment_history().get(id))
+                    .filter_map(|t| t[method])
+                    .map(|t| t.total_seconds())
+                    .collect();
+                let segment_time = if times.is_empty() {
+                    segment.best_segment_time()[method]
+                } else {
+                    Some(TimeSpan::from_seconds(times.iter().sum::<f64>() / times.len() as f64))
+                };
+                total = total.zip(segment_time).map(|(total, t)| total + t);
+                segment.comparison_mut(&self.name)[method] = total;
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct BalancedTarget {
+    name: String,
+    target: TimeSpan,
+}
+
+impl ComparisonGenerator for BalancedTarget {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn generate(&mut self, segments: &mut [Segment], _attempts: &[Attempt]) {
+        for method in TimingMethod::all() {
+            let golds: Vec<Option<TimeSpan>> = segments.iter().map(|s| s.best_segment_time()[method]).collect();
+            for (segment, time) in segments.iter_mut().zip(distribute(self.target, &golds)) {
+                segment.comparison_mut(&self.name)[method] = time;
+            }
+        }
+    }
+}
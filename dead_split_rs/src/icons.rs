@@ -0,0 +1,28 @@
+use godot::{
+    classes::{Image, ImageTexture},
+    global::Error,
+    prelude::*,
+};
+
+// Icons are stored in runs as raw image files, usually png but livesplit allows anything it can load.
+// The format is guessed from the file's magic bytes.
+pub fn texture_from_bytes(bytes: &[u8]) -> Option<Gd<ImageTexture>> {
+    if bytes.is_empty() {
+        return None;
+    }
+    let buffer = PackedByteArray::from(bytes);
+    let mut image = Image::new_gd();
+    let err = if bytes.starts_with(&[0xFF, 0xD8]) {
+        image.load_jpg_from_buffer(&buffer)
+    } else if bytes.starts_with(b"BM") {
+        image.load_bmp_from_buffer(&buffer)
+    } else if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(&b"WEBP"[..]) {
+        image.load_webp_from_buffer(&buffer)
+    } else {
+        image.load_png_from_buffer(&buffer)
+    };
+    if err != Error::OK {
+        return None;
+    }
+    ImageTexture::create_from_image(&image)
+}
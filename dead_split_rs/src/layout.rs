@@ -0,0 +1,209 @@
+use godot::prelude::*;
+use livesplit_core::{
+    component::{
+        blank_space, current_comparison, current_pace, delta, detailed_timer, graph, pb_chance,
+        possible_time_save, previous_segment, segment_time, separator, splits, sum_of_best,
+        text::{self, TextState},
+        timer, title, total_playtime,
+    },
+    layout::{ComponentState, LayoutState},
+    settings::{Color as LsColor, SemanticColor},
+    Component,
+};
+
+// Names used by godot to pick layout components, matching livesplit's component names without spaces
+pub fn component_from_name(name: &str) -> Option<Component> {
+    Some(match name {
+        "BlankSpace" => blank_space::Component::new().into(),
+        "CurrentComparison" => current_comparison::Component::new().into(),
+        "CurrentPace" => current_pace::Component::new().into(),
+        "Delta" => delta::Component::new().into(),
+        "DetailedTimer" => detailed_timer::Component::new().into(),
+        "Graph" => graph::Component::new().into(),
+        "PbChance" => pb_chance::Component::new().into(),
+        "PossibleTimeSave" => possible_time_save::Component::new().into(),
+        "PreviousSegment" => previous_segment::Component::new().into(),
+        "SegmentTime" => segment_time::Component::new().into(),
+        "Separator" => separator::Component::new().into(),
+        "Splits" => splits::Component::new().into(),
+        "SumOfBest" => sum_of_best::Component::new().into(),
+        "Text" => text::Component::new().into(),
+        "Timer" => timer::Component::new().into(),
+        "Title" => title::Component::new().into(),
+        "TotalPlaytime" => total_playtime::Component::new().into(),
+        _ => return None,
+    })
+}
+
+// Inverse of component_from_name
+pub fn component_name(component: &Component) -> &'static str {
+    match component {
+        Component::BlankSpace(_) => "BlankSpace",
+        Component::CurrentComparison(_) => "CurrentComparison",
+        Component::CurrentPace(_) => "CurrentPace",
+        Component::Delta(_) => "Delta",
+        Component::DetailedTimer(_) => "DetailedTimer",
+        Component::Graph(_) => "Graph",
+        Component::PbChance(_) => "PbChance",
+        Component::PossibleTimeSave(_) => "PossibleTimeSave",
+        Component::PreviousSegment(_) => "PreviousSegment",
+        Component::SegmentTime(_) => "SegmentTime",
+        Component::Separator(_) => "Separator",
+        Component::Splits(_) => "Splits",
+        Component::SumOfBest(_) => "SumOfBest",
+        Component::Text(_) => "Text",
+        Component::Timer(_) => "Timer",
+        Component::Title(_) => "Title",
+        Component::TotalPlaytime(_) => "TotalPlaytime",
+    }
+}
+
+fn color(c: LsColor) -> Color {
+    Color::from_rgba(c.red, c.green, c.blue, c.alpha)
+}
+
+fn optional_color(c: Option<LsColor>) -> Variant {
+    c.map_or(Variant::nil(), |c| color(c).to_variant())
+}
+
+// Semantic colors go to godot by name ("AheadGainingTime", "BehindLosingTime", "BestSegment", ...)
+// so the theme can pick the actual color
+fn semantic_name(semantic: SemanticColor) -> GString {
+    GString::from(format!("{semantic:?}").as_str())
+}
+
+fn strings<'a>(items: impl IntoIterator<Item = &'a str>) -> PackedStringArray {
+    PackedStringArray::from_iter(items.into_iter().map(GString::from))
+}
+
+fn timer_dict(state: &timer::State) -> Dictionary {
+    let mut dict = Dictionary::new();
+    dict.set("time", &*state.time);
+    dict.set("fraction", &*state.fraction);
+    dict.set("semantic_color", semantic_name(state.semantic_color));
+    dict.set("top_color", color(state.top_color));
+    dict.set("bottom_color", color(state.bottom_color));
+    dict
+}
+
+// Each component is { "type": String, ... } with type being the state's kind rather than the component's,
+// e.g. every key/value component (Delta, SumOfBest, PbChance, ...) is "KeyValue".
+// Backgrounds and sizes are left to the godot theme.
+pub fn component_state_to_dict(state: &ComponentState) -> Dictionary {
+    let mut dict = Dictionary::new();
+    match state {
+        ComponentState::BlankSpace(_) => {
+            dict.set("type", "BlankSpace");
+        }
+        ComponentState::Separator(_) => {
+            dict.set("type", "Separator");
+        }
+        ComponentState::KeyValue(state) => {
+            dict.set("type", "KeyValue");
+            dict.set("key", &*state.key);
+            dict.set("value", &*state.value);
+            dict.set("key_abbreviations", strings(state.key_abbreviations.iter().map(|s| &**s)));
+            dict.set("key_color", optional_color(state.key_color));
+            dict.set("value_color", optional_color(state.value_color));
+            dict.set("semantic_color", semantic_name(state.semantic_color));
+            dict.set("display_two_rows", state.display_two_rows);
+        }
+        ComponentState::Timer(state) => {
+            dict = timer_dict(state);
+            dict.set("type", "Timer");
+        }
+        ComponentState::DetailedTimer(state) => {
+            dict.set("type", "DetailedTimer");
+            dict.set("timer", timer_dict(&state.timer));
+            dict.set("segment_timer", timer_dict(&state.segment_timer));
+            dict.set("segment_name", state.segment_name.as_deref().unwrap_or_default());
+            for (key, comparison) in [("comparison1", &state.comparison1), ("comparison2", &state.comparison2)] {
+                let value = match comparison {
+                    Some(comparison) => {
+                        let mut c = Dictionary::new();
+                        c.set("name", &*comparison.name);
+                        c.set("time", &*comparison.time);
+                        c.to_variant()
+                    }
+                    None => Variant::nil(),
+                };
+                dict.set(key, value);
+            }
+        }
+        ComponentState::Title(state) => {
+            dict.set("type", "Title");
+            dict.set("line1", strings(state.line1.iter().map(|s| &**s)));
+            dict.set("line2", strings(state.line2.iter().map(|s| &**s)));
+            dict.set("text_color", optional_color(state.text_color));
+            dict.set("is_centered", state.is_centered);
+            dict.set("attempts", state.attempts.map_or(Variant::nil(), |a| (a as i64).to_variant()));
+            dict.set("finished_runs", state.finished_runs.map_or(Variant::nil(), |r| (r as i64).to_variant()));
+        }
+        ComponentState::Text(state) => {
+            dict.set("type", "Text");
+            match &state.text {
+                TextState::Center(center) => {
+                    dict.set("center", &**center);
+                }
+                TextState::Split(left, right) => {
+                    dict.set("left", &**left);
+                    dict.set("right", &**right);
+                }
+            }
+            dict.set("left_center_color", color(state.left_center_color));
+            dict.set("right_color", color(state.right_color));
+        }
+        ComponentState::Splits(state) => {
+            dict.set("type", "Splits");
+            dict.set(
+                "column_labels",
+                state
+                    .column_labels
+                    .as_ref()
+                    .map(|labels| strings(labels.iter().map(|s| &**s)))
+                    .unwrap_or_default(),
+            );
+            let splits = Array::from_iter(state.splits.iter().map(|split| {
+                let columns = Array::from_iter(split.columns.iter().map(|column| {
+                    let mut c = Dictionary::new();
+                    c.set("value", &*column.value);
+                    c.set("semantic_color", semantic_name(column.semantic_color));
+                    c.set("visual_color", color(column.visual_color));
+                    c
+                }));
+                let mut s = Dictionary::new();
+                s.set("index", split.index as i64);
+                s.set("name", &*split.name);
+                s.set("is_current_split", split.is_current_split);
+                s.set("columns", columns);
+                s
+            }));
+            dict.set("splits", splits);
+        }
+        ComponentState::Graph(state) => {
+            dict.set("type", "Graph");
+            let points = Array::from_iter(state.points.iter().map(|point| {
+                let mut p = Dictionary::new();
+                p.set("x", point.x);
+                p.set("y", point.y);
+                p.set("is_best_segment", point.is_best_segment);
+                p
+            }));
+            dict.set("points", points);
+            dict.set("middle", state.middle);
+            dict.set("is_live_delta_active", state.is_live_delta_active);
+            dict.set("is_flipped", state.is_flipped);
+        }
+    }
+    dict
+}
+
+// { "components": Array } with every component in order, see component_state_to_dict
+pub fn layout_state_to_dict(state: &LayoutState) -> Dictionary {
+    let mut dict = Dictionary::new();
+    dict.set(
+        "components",
+        Array::from_iter(state.components.iter().map(component_state_to_dict)),
+    );
+    dict
+}
@@ -1,17 +1,37 @@
-use std::sync::{RwLockReadGuard, RwLockWriteGuard};
+use std::{
+    path::PathBuf,
+    sync::{RwLockReadGuard, RwLockWriteGuard},
+};
 
 use autosplitter_manager::AutosplitterManager;
+use events::{EventQueue, TimerEvent};
 use global_hotkey::GlobalHotKeyManager;
 use godot::prelude::*;
 use hotkey_manager::HotkeyManager;
-use livesplit_core::{Run, Segment, SharedTimer, Timer, hotkey::Hook};
+use livesplit_core::{Layout, layout::LayoutState, Run, Segment, SharedTimer, TimeSpan, Timer, TimerPhase, hotkey::Hook, settings::ImageCache};
+use recovery::{Journal, Recovery};
 use read_process_memory::ProcessHandle;
 use sysinfo::{Pid, ProcessRefreshKind, RefreshKind, System};
 
 mod editable_run;
 mod timer;
 mod hotkey_manager;
+mod icons;
 mod autosplitter_manager;
+mod diff;
+mod events;
+mod export;
+mod generators;
+mod layout;
+mod lsl;
+mod persistence;
+mod recovery;
+mod run_settings;
+mod segments;
+mod subsplits;
+#[cfg(test)]
+mod test_helpers;
+mod validation;
 
 struct DeadSplitRust;
 
@@ -38,10 +58,35 @@ pub struct DeadSplitTimer {
     pub current_split_index: i32,
     #[var]
     pub timer_phase: u8,
+    // Seconds left of the run's start delay, 0 when there's no countdown going on
+    #[var]
+    pub countdown_remaining: f64,
+    // Bumped whenever the run or the timer's position in it changes, so the UI knows when to rebuild
+    #[var]
+    pub revision: i64,
+    // Last comparison seen by process, used to emit comparison_changed no matter who switched it
+    current_comparison: String,
+    // Saves the run to run_path after every reset, keeping autosave_backups old copies
+    #[var]
+    pub autosave: bool,
+    #[var]
+    pub autosave_backups: i32,
+    run_path: Option<PathBuf>,
+    // Seconds since the attempt journal was last written
+    journal_timer: f64,
+    // Set while a recovered attempt is in progress, see Journal::restore
+    recovery: Option<Recovery>,
+    // Filled by timer actions from any thread, emitted as signals in process
+    events: EventQueue,
     hotkey_mgr: HotkeyManager,
     system: System,
     attached_process: Option<ProcessData>,
     autosplitter_manager: Option<AutosplitterManager>,
+    // livesplit-core layout, only used to calculate component states for godot to render
+    layout: Layout,
+    // Kept between get_layout_state calls so it can be updated in place
+    layout_state: LayoutState,
+    image_cache: ImageCache,
 
     base: Base<Node>,
 }
@@ -67,6 +112,15 @@ impl INode for DeadSplitTimer {
             current_game_time: 0.0,
             current_split_index: -1,
             timer_phase: 0,
+            countdown_remaining: 0.0,
+            revision: 0,
+            current_comparison: String::from(livesplit_core::comparison::personal_best::NAME),
+            autosave: false,
+            autosave_backups: 3,
+            run_path: None,
+            journal_timer: 0.0,
+            recovery: None,
+            events: EventQueue::default(),
             // Starts by default with a wayland hook.
             // This should be reloaded when the timer's settings are loaded.
             hotkey_mgr: HotkeyManager::new_wayland(Hook::new().expect("Failed to create hotkey hook")),
@@ -75,24 +129,111 @@ impl INode for DeadSplitTimer {
             )),
             attached_process: None,
             autosplitter_manager: None,
+            layout: Layout::default_layout(),
+            layout_state: LayoutState::default(),
+            image_cache: ImageCache::new(),
             base,
         }
     }
 
-    fn process(&mut self, _delta: f64) {
+    fn process(&mut self, delta: f64) {
         // Updates displayed properties from a snapshot every frame
         // Make sure binding is dropped before we need to access self's hotkey data
+        let comparison_changed;
+        let countdown_finished;
         {
             let binding = timer_read(&self.timer);
             let snapshot = binding.snapshot();
             let t = snapshot.current_time();
             self.current_time = t.real_time.unwrap_or_default().total_seconds();
             self.current_game_time = t.game_time.unwrap_or_default().total_seconds();
-            self.current_split_index = match snapshot.current_split_index() {
+            let split_index = match snapshot.current_split_index() {
                 Some(i) => i as i32,
                 None => -1,
             };
-            self.timer_phase = snapshot.current_phase() as u8;
+            let phase = snapshot.current_phase() as u8;
+            // A start delay is a negative offset, the timer counts up from it to 0
+            let was_counting_down = self.countdown_remaining > 0.0;
+            self.countdown_remaining = match snapshot.current_phase() {
+                TimerPhase::NotRunning => 0.0,
+                _ => (-self.current_time).max(0.0),
+            };
+            countdown_finished = was_counting_down
+                && self.countdown_remaining == 0.0
+                && snapshot.current_phase() != TimerPhase::NotRunning;
+            comparison_changed = snapshot.current_comparison() != self.current_comparison;
+            if comparison_changed {
+                self.current_comparison = snapshot.current_comparison().to_owned();
+            }
+            // Catches changes made outside of godot calls too, e.g. by the autosplitter thread
+            if comparison_changed || split_index != self.current_split_index || phase != self.timer_phase {
+                self.revision += 1;
+            }
+            self.current_split_index = split_index;
+            self.timer_phase = phase;
+        }
+
+        if comparison_changed {
+            let comp = GString::from(self.current_comparison.as_str());
+            self.base_mut()
+                .clone()
+                .upcast::<Object>()
+                .emit_signal("comparison_changed", &[Variant::from(comp)]);
+        }
+
+        if countdown_finished {
+            self.base_mut()
+                .clone()
+                .upcast::<Object>()
+                .emit_signal("countdown_finished", &[]);
+        }
+
+        let mut was_reset = false;
+        let mut reset_saved = false;
+        for event in self.events.drain() {
+            if let TimerEvent::Reset(saved) = event {
+                was_reset = true;
+                reset_saved = saved;
+            }
+            // Game time always runs along with the timer, so it can be switched to mid-run even without an
+            // autosplitter, and autosplitters only have to pause it
+            if matches!(event, TimerEvent::Started) {
+                let mut binding = timer_write(&self.timer);
+                if !binding.is_game_time_initialized() {
+                    let _ = binding.initialize_game_time();
+                }
+            }
+            let (signal, args) = match event {
+                TimerEvent::Started => ("started", vec![]),
+                TimerEvent::Split(idx) => ("split", vec![Variant::from(idx)]),
+                TimerEvent::SplitUndone => ("split_undone", vec![]),
+                TimerEvent::SplitSkipped => ("split_skipped", vec![]),
+                TimerEvent::Paused => ("paused", vec![]),
+                TimerEvent::Resumed => ("resumed", vec![]),
+                TimerEvent::Reset(saved) => ("run_reset", vec![Variant::from(saved)]),
+                TimerEvent::RunFinished(is_pb) => ("run_finished", vec![Variant::from(is_pb)]),
+            };
+            self.base_mut()
+                .clone()
+                .upcast::<Object>()
+                .emit_signal(signal, &args);
+        }
+
+        if was_reset {
+            if let Some(recovery) = self.recovery.take() {
+                self.finish_recovery(&recovery, reset_saved);
+            }
+            // Only resets change the run, finishing doesn't touch it until the attempt is reset
+            if self.autosave {
+                self.autosave_run();
+            }
+            self.discard_journal();
+        }
+
+        self.journal_timer += delta;
+        if self.journal_timer >= JOURNAL_INTERVAL {
+            self.journal_timer = 0.0;
+            self.write_journal();
         }
 
         // Check for hotkey presses
@@ -106,6 +247,77 @@ impl INode for DeadSplitTimer {
     }
 }
 
+// Seconds between writes of the attempt journal
+const JOURNAL_INTERVAL: f64 = 1.0;
+
+impl DeadSplitTimer {
+    fn write_journal(&self) {
+        let Some(path) = &self.run_path else {
+            return;
+        };
+        let binding = timer_read(&self.timer);
+        if let Some(journal) = Journal::from_timer(&binding, self.recovery.as_ref()) {
+            let _ = persistence::write_atomic(&recovery::journal_path(path), &journal.write());
+        }
+    }
+
+    fn discard_journal(&self) {
+        if let Some(path) = &self.run_path {
+            let _ = std::fs::remove_file(recovery::journal_path(path));
+        }
+    }
+
+    // Only call this while the timer isn't running, setting the run resets the timer
+    fn finish_recovery(&self, recovery: &Recovery, saved: bool) {
+        let mut binding = timer_write(&self.timer);
+        let mut run = binding.run().clone();
+        recovery.finish(&mut run, saved);
+        let _ = binding.set_run(run);
+    }
+
+    // Call before replacing the run. Replacing it throws away an attempt in progress without a reset,
+    // so its journal would be left behind and picked back up the next time the run is loaded.
+    // A recovered attempt is discarded here so its offset doesn't stay in the run.
+    fn drop_attempt(&mut self) {
+        if timer_read(&self.timer).current_phase() != TimerPhase::NotRunning {
+            self.discard_journal();
+        }
+        if let Some(recovery) = self.recovery.take() {
+            let _ = timer_write(&self.timer).reset(false);
+            self.finish_recovery(&recovery, false);
+        }
+    }
+
+    // The run as it should be saved or edited. While a recovered attempt runs, the run's offset is the
+    // attempt's elapsed time (see Journal::restore), which mustn't end up in the file.
+    fn clean_run(&self) -> Run {
+        let binding = timer_read(&self.timer);
+        match &self.recovery {
+            Some(recovery) => recovery.original_run(binding.run()),
+            None => binding.run().clone(),
+        }
+    }
+
+    // Switches to the run's own timing method, if it has one
+    fn apply_run_timing_method(&self) {
+        let mut binding = timer_write(&self.timer);
+        if let Some(method) = run_settings::timing_method(binding.run()) {
+            binding.set_current_timing_method(method);
+        }
+    }
+
+    fn autosave_run(&self) {
+        let Some(path) = &self.run_path else {
+            return;
+        };
+        let binding = timer_read(&self.timer);
+        let backups = self.autosave_backups.max(0) as usize;
+        if let Err(e) = persistence::save_run_atomic(binding.run(), path, backups) {
+            godot_error!("Autosave to {} failed: {e}", path.display());
+        }
+    }
+}
+
 fn get_default_run() -> Run {
     let mut run = Run::new();
     run.push_segment(Segment::new("Time"));
@@ -0,0 +1,425 @@
+use std::fmt::Write;
+
+use godot::prelude::*;
+use livesplit_core::{
+    layout::{parser, GeneralSettings, LayoutDirection},
+    settings::{Color as LsColor, Font, FontStyle, FontWeight, Gradient, LayoutBackground},
+    Component, Layout,
+};
+
+use crate::layout::component_name;
+
+// Conversion between livesplit .lsl layouts and the timer's settings.
+// Keys in the settings dictionary match the variables in timer_settings.gd.
+
+pub fn parse_lsl(source: &str) -> Option<Layout> {
+    parser::parse(source).ok()
+}
+
+pub fn layout_to_settings(layout: &Layout) -> Dictionary {
+    let mut dict = Dictionary::new();
+    let mut show_title = false;
+    let mut show_splits = false;
+
+    for component in &layout.components {
+        match component {
+            Component::Title(c) => {
+                let settings = c.settings();
+                show_title = true;
+                dict.set("show_attempt_count", settings.show_attempt_count);
+                dict.set("show_finished_runs", settings.show_finished_runs_count);
+                dict.set("title_one_line", settings.display_as_single_line);
+            }
+            Component::Splits(c) => {
+                let settings = c.settings();
+                show_splits = true;
+                dict.set("shown_splits", settings.visual_split_count as i64);
+                dict.set("shown_upcoming_splits", settings.split_preview_count as i64);
+                dict.set("last_split_pinned", settings.always_show_last_split);
+            }
+            _ => {}
+        }
+    }
+    dict.set("show_title", show_title);
+    dict.set("show_splits", show_splits);
+    dict.set(
+        "components",
+        PackedStringArray::from_iter(layout.components.iter().map(|c| GString::from(component_name(c)))),
+    );
+
+    let general = layout.general_settings();
+    let mut colors = Dictionary::new();
+    for (name, color) in general_colors(general) {
+        colors.set(name, Color::from_rgba(color.red, color.green, color.blue, color.alpha));
+    }
+    dict.set("colors", colors);
+
+    // Only the family survives, godot themes pick fonts by name
+    let mut fonts = Dictionary::new();
+    for (name, font) in [
+        ("timer", &general.timer_font),
+        ("times", &general.times_font),
+        ("text", &general.text_font),
+    ] {
+        if let Some(Font { family, .. }) = font {
+            fonts.set(name, family.as_str());
+        }
+    }
+    dict.set("fonts", fonts);
+
+    dict
+}
+
+// Color names are the livesplit xml element names without the "Color" suffix
+fn general_colors(general: &GeneralSettings) -> [(&'static str, LsColor); 11] {
+    [
+        ("Text", general.text_color),
+        ("ThinSeparators", general.thin_separators_color),
+        ("Separators", general.separators_color),
+        ("PersonalBest", general.personal_best_color),
+        ("AheadGainingTime", general.ahead_gaining_time_color),
+        ("AheadLosingTime", general.ahead_losing_time_color),
+        ("BehindGainingTime", general.behind_gaining_time_color),
+        ("BehindLosingTime", general.behind_losing_time_color),
+        ("BestSegment", general.best_segment_color),
+        ("NotRunning", general.not_running_color),
+        ("Paused", general.paused_color),
+    ]
+}
+
+// Livesplit's component dlls. Components without an equivalent are left out of the file.
+fn component_path(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "BlankSpace" => "LiveSplit.BlankSpace.dll",
+        "CurrentComparison" => "LiveSplit.CurrentComparison.dll",
+        "CurrentPace" => "LiveSplit.RunPrediction.dll",
+        "Delta" => "LiveSplit.Delta.dll",
+        "DetailedTimer" => "LiveSplit.DetailedTimer.dll",
+        "Graph" => "LiveSplit.Graph.dll",
+        "PbChance" => "PBChance.dll",
+        "PossibleTimeSave" => "LiveSplit.PossibleTimeSave.dll",
+        "PreviousSegment" => "LiveSplit.PreviousSegment.dll",
+        "Separator" => "",
+        "Splits" => "LiveSplit.Splits.dll",
+        "SumOfBest" => "LiveSplit.SumOfBest.dll",
+        "Text" => "LiveSplit.Text.dll",
+        "Timer" => "LiveSplit.Timer.dll",
+        "Title" => "LiveSplit.Title.dll",
+        "TotalPlaytime" => "LiveSplit.TotalPlaytime.dll",
+        _ => return None,
+    })
+}
+
+fn dict_bool(dict: &Dictionary, key: &str, default: bool) -> bool {
+    dict.get(key)
+        .and_then(|v| v.try_to::<bool>().ok())
+        .unwrap_or(default)
+}
+
+fn dict_int(dict: &Dictionary, key: &str, default: i64) -> i64 {
+    dict.get(key)
+        .and_then(|v| v.try_to::<i64>().ok())
+        .unwrap_or(default)
+}
+
+// livesplit stores colors as ARGB hex
+fn color_hex(color: LsColor) -> String {
+    let [r, g, b, a] = [color.red, color.green, color.blue, color.alpha].map(|c| (c * 255.0).round() as u8);
+    format!("{a:02X}{r:02X}{g:02X}{b:02X}")
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+// .NET length prefixed string, the length being a 7 bit varint
+fn push_string(out: &mut Vec<u8>, s: &str) {
+    let mut len = s.len();
+    while len >= 0x80 {
+        out.push((len & 0x7F) as u8 | 0x80);
+        len >>= 7;
+    }
+    out.push(len as u8);
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn push_i32(out: &mut Vec<u8>, n: i32) {
+    out.extend_from_slice(&n.to_le_bytes());
+}
+
+// livesplit keeps fonts as a System.Drawing.Font put through .NET's BinaryFormatter, base64 encoded.
+// The stream is what livesplit itself writes: the font's Name, Size, Style and Unit, the last two being enums.
+// livesplit-core only reads the family and the bold and italic flags, the size is livesplit's default.
+fn font_base64(font: &Font, size: f32) -> String {
+    const DRAWING: &str = "System.Drawing, Version=4.0.0.0, Culture=neutral, PublicKeyToken=b03f5f7f11d50a3a";
+    let bold = matches!(
+        font.weight,
+        FontWeight::SemiBold | FontWeight::Bold | FontWeight::ExtraBold | FontWeight::Black | FontWeight::ExtraBlack
+    );
+    let italic = !matches!(font.style, FontStyle::Normal);
+    let style = bold as i32 | (italic as i32) << 1;
+    // GraphicsUnit.Pixel
+    let unit = 2;
+
+    let mut out = Vec::new();
+    // Header, the root being object 1
+    out.push(0x00);
+    for n in [1, -1, 1, 0] {
+        push_i32(&mut out, n);
+    }
+    // System.Drawing as library 2
+    out.push(0x0C);
+    push_i32(&mut out, 2);
+    push_string(&mut out, DRAWING);
+    // The font as object 1, with its member names and types
+    out.push(0x05);
+    push_i32(&mut out, 1);
+    push_string(&mut out, "System.Drawing.Font");
+    push_i32(&mut out, 4);
+    for name in ["Name", "Size", "Style", "Unit"] {
+        push_string(&mut out, name);
+    }
+    // String, primitive, class, class, then the primitive's type (Single) and the classes
+    out.extend_from_slice(&[1, 0, 4, 4, 11]);
+    for enum_name in ["System.Drawing.FontStyle", "System.Drawing.GraphicsUnit"] {
+        push_string(&mut out, enum_name);
+        push_i32(&mut out, 2);
+    }
+    push_i32(&mut out, 2);
+    // Member values: the name as string object 3, the size, then the enums as inline value types
+    out.push(0x06);
+    push_i32(&mut out, 3);
+    push_string(&mut out, &font.family);
+    out.extend_from_slice(&size.to_le_bytes());
+    for (id, enum_name, value) in [(-3, "System.Drawing.FontStyle", style), (-4, "System.Drawing.GraphicsUnit", unit)] {
+        out.push(0x05);
+        push_i32(&mut out, id);
+        push_string(&mut out, enum_name);
+        push_i32(&mut out, 1);
+        push_string(&mut out, "value__");
+        // Primitive Int32
+        out.extend_from_slice(&[0, 8]);
+        push_i32(&mut out, 2);
+        push_i32(&mut out, value);
+    }
+    // Message end
+    out.push(0x0B);
+    base64(&out)
+}
+
+// Everything under the layout's <Settings> that livesplit-core reads: the general colors, the fonts and the
+// background. A background image is written as a transparent background, the picture itself isn't kept.
+fn write_general_settings(out: &mut String, general: &GeneralSettings) {
+    for (name, color) in general_colors(general) {
+        let _ = writeln!(out, "    <{name}Color>{}</{name}Color>", color_hex(color));
+    }
+
+    let transparent = LsColor::rgba(0.0, 0.0, 0.0, 0.0);
+    let (kind, first, second) = match &general.background {
+        LayoutBackground::Gradient(Gradient::Transparent) | LayoutBackground::Image(_) => {
+            ("SolidColor", transparent, transparent)
+        }
+        LayoutBackground::Gradient(Gradient::Plain(color)) => ("SolidColor", *color, transparent),
+        LayoutBackground::Gradient(Gradient::Vertical(top, bottom)) => ("VerticalGradient", *top, *bottom),
+        LayoutBackground::Gradient(Gradient::Horizontal(left, right)) => ("HorizontalGradient", *left, *right),
+    };
+    let _ = writeln!(out, "    <BackgroundColor>{}</BackgroundColor>", color_hex(first));
+    let _ = writeln!(out, "    <BackgroundColor2>{}</BackgroundColor2>", color_hex(second));
+    let _ = writeln!(out, "    <BackgroundType>{kind}</BackgroundType>");
+
+    // Fonts left unset use livesplit's defaults
+    for (name, font, size) in [
+        ("TimerFont", &general.timer_font, 50.0),
+        ("TimesFont", &general.times_font, 18.0),
+        ("TextFont", &general.text_font, 18.0),
+    ] {
+        if let Some(font) = font {
+            let _ = writeln!(out, "    <{name}>{}</{name}>", font_base64(font, size));
+        }
+    }
+}
+
+// The timer settings write_lsl takes from godot's settings dictionary
+struct LslSettings {
+    show_title: bool,
+    show_splits: bool,
+    window_size: Vector2i,
+    show_attempt_count: bool,
+    show_finished_runs: bool,
+    title_one_line: bool,
+    shown_splits: i64,
+    shown_upcoming_splits: i64,
+    last_split_pinned: bool,
+}
+
+impl Default for LslSettings {
+    fn default() -> Self {
+        Self {
+            show_title: true,
+            show_splits: true,
+            window_size: Vector2i::new(750, 750),
+            show_attempt_count: true,
+            show_finished_runs: true,
+            title_one_line: false,
+            shown_splits: 10,
+            shown_upcoming_splits: 1,
+            last_split_pinned: true,
+        }
+    }
+}
+
+impl LslSettings {
+    fn from_dict(settings: &Dictionary) -> Self {
+        let defaults = Self::default();
+        Self {
+            show_title: dict_bool(settings, "show_title", defaults.show_title),
+            show_splits: dict_bool(settings, "show_splits", defaults.show_splits),
+            window_size: settings
+                .get("window_size")
+                .and_then(|v| v.try_to::<Vector2i>().ok())
+                .unwrap_or(defaults.window_size),
+            show_attempt_count: dict_bool(settings, "show_attempt_count", defaults.show_attempt_count),
+            show_finished_runs: dict_bool(settings, "show_finished_runs", defaults.show_finished_runs),
+            title_one_line: dict_bool(settings, "title_one_line", defaults.title_one_line),
+            shown_splits: dict_int(settings, "shown_splits", defaults.shown_splits),
+            shown_upcoming_splits: dict_int(settings, "shown_upcoming_splits", defaults.shown_upcoming_splits),
+            last_split_pinned: dict_bool(settings, "last_split_pinned", defaults.last_split_pinned),
+        }
+    }
+}
+
+// Writes the layout's components in order, with the title and splits settings taken from the timer settings.
+// Settings livesplit has but the timer doesn't are left out, so livesplit falls back to its defaults for them.
+pub fn write_lsl(layout: &Layout, settings: &Dictionary) -> String {
+    write_layout(layout, &LslSettings::from_dict(settings))
+}
+
+fn write_layout(layout: &Layout, settings: &LslSettings) -> String {
+    let general = layout.general_settings();
+    let size = settings.window_size;
+    let (mode, vertical, horizontal) = match general.direction {
+        LayoutDirection::Vertical => ("Vertical", (size.x, size.y), (-1, -1)),
+        LayoutDirection::Horizontal => ("Horizontal", (-1, -1), (size.x, size.y)),
+    };
+
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<Layout version=\"1.6.1\">\n");
+    let _ = writeln!(out, "  <Mode>{mode}</Mode>");
+    let _ = writeln!(out, "  <VerticalWidth>{}</VerticalWidth>", vertical.0);
+    let _ = writeln!(out, "  <VerticalHeight>{}</VerticalHeight>", vertical.1);
+    let _ = writeln!(out, "  <HorizontalWidth>{}</HorizontalWidth>", horizontal.0);
+    let _ = writeln!(out, "  <HorizontalHeight>{}</HorizontalHeight>", horizontal.1);
+
+    out.push_str("  <Settings>\n");
+    write_general_settings(&mut out, general);
+    out.push_str("  </Settings>\n");
+
+    out.push_str("  <Components>\n");
+    for component in &layout.components {
+        let name = component_name(component);
+        let Some(path) = component_path(name) else {
+            continue;
+        };
+        let component_settings = match name {
+            "Title" if !settings.show_title => continue,
+            "Splits" if !settings.show_splits => continue,
+            "Title" => format!(
+                "      <ShowAttemptCount>{}</ShowAttemptCount>\n      <ShowFinishedRunsCount>{}</ShowFinishedRunsCount>\n      <SingleLine>{}</SingleLine>\n",
+                bool_xml(settings.show_attempt_count),
+                bool_xml(settings.show_finished_runs),
+                bool_xml(settings.title_one_line),
+            ),
+            "Splits" => format!(
+                "      <VisualSplitCount>{}</VisualSplitCount>\n      <SplitPreviewCount>{}</SplitPreviewCount>\n      <LockLastSplit>{}</LockLastSplit>\n",
+                settings.shown_splits,
+                settings.shown_upcoming_splits,
+                bool_xml(settings.last_split_pinned),
+            ),
+            _ => String::new(),
+        };
+        let _ = write!(
+            out,
+            "    <Component>\n      <Path>{path}</Path>\n      <Settings>\n{component_settings}      </Settings>\n    </Component>\n"
+        );
+    }
+    out.push_str("  </Components>\n</Layout>\n");
+
+    out
+}
+
+fn bool_xml(b: bool) -> &'static str {
+    if b {
+        "True"
+    } else {
+        "False"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use livesplit_core::{component::sum_of_best, settings::FontStretch};
+
+    use super::*;
+
+    #[test]
+    fn base64_pads() {
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn layout_round_trips_through_livesplit_core() {
+        let mut layout = Layout::default_layout();
+        layout.push(sum_of_best::Component::new());
+        let general = layout.general_settings_mut();
+        general.text_color = LsColor::rgba(1.0, 0.0, 0.0, 1.0);
+        general.background = LayoutBackground::Gradient(Gradient::Vertical(
+            LsColor::rgba(0.0, 0.0, 1.0, 1.0),
+            LsColor::rgba(0.0, 1.0, 0.0, 1.0),
+        ));
+        general.timer_font = Some(Font {
+            family: String::from("Fira Sans"),
+            style: FontStyle::Italic,
+            weight: FontWeight::Bold,
+            stretch: FontStretch::Normal,
+        });
+        general.text_font = Some(Font {
+            family: String::from("Noto Sans"),
+            style: FontStyle::Normal,
+            weight: FontWeight::Normal,
+            stretch: FontStretch::Normal,
+        });
+
+        let source = write_layout(&layout, &LslSettings::default());
+        let parsed = parser::parse(&source).expect("written layout should parse");
+
+        let names = |layout: &Layout| layout.components.iter().map(component_name).collect::<Vec<_>>();
+        assert_eq!(names(&parsed), names(&layout));
+        assert!(names(&parsed).contains(&"SumOfBest"));
+
+        let (ours, theirs) = (layout.general_settings(), parsed.general_settings());
+        let colors = |general: &GeneralSettings| general_colors(general).map(|(_, c)| color_hex(c));
+        assert_eq!(colors(theirs), colors(ours));
+        assert_eq!(theirs.background, ours.background);
+        let timer_font = theirs.timer_font.as_ref().expect("timer font should be read back");
+        assert_eq!(timer_font.family, "Fira Sans");
+        assert_eq!(timer_font.weight, FontWeight::Bold);
+        assert_eq!(timer_font.style, FontStyle::Italic);
+        assert_eq!(theirs.text_font.as_ref().map(|f| f.family.as_str()), Some("Noto Sans"));
+        assert!(theirs.times_font.is_none());
+    }
+}
@@ -0,0 +1,140 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use godot::builtin::Dictionary;
+use livesplit_core::{
+    run::{
+        parser::composite,
+        saver::livesplit::{self, IoWrite},
+    },
+    Run,
+};
+
+// path.lss -> path.lss.1.bak, path.lss.2.bak, ...
+fn backup_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(format!(".{n}.bak"));
+    PathBuf::from(name)
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".tmp");
+    PathBuf::from(name)
+}
+
+// Moves every backup up by one, dropping the oldest, then copies the current file in as the newest.
+// The current file is copied rather than moved so there is never a moment without a run file.
+fn rotate_backups(path: &Path, backups: usize) -> io::Result<()> {
+    if backups == 0 || !path.exists() {
+        return Ok(());
+    }
+    let _ = fs::remove_file(backup_path(path, backups));
+    for n in (1..backups).rev() {
+        let from = backup_path(path, n);
+        if from.exists() {
+            fs::rename(from, backup_path(path, n + 1))?;
+        }
+    }
+    fs::copy(path, backup_path(path, 1))?;
+    Ok(())
+}
+
+// Same temp file and rename as save_run_atomic, for small files that aren't runs
+pub fn write_atomic(path: &Path, contents: &str) -> io::Result<()> {
+    let temp = temp_path(path);
+    fs::write(&temp, contents)?;
+    fs::rename(temp, path)
+}
+
+// Writes the run to a temp file next to the target and renames it over the target,
+// so a crash mid-save can't leave a half-written run behind.
+pub fn save_run_atomic(run: &Run, path: &Path, backups: usize) -> io::Result<()> {
+    let temp = temp_path(path);
+    {
+        let mut writer = BufWriter::new(File::create(&temp)?);
+        livesplit::save_run(run, IoWrite(&mut writer)).map_err(io::Error::other)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+    }
+    rotate_backups(path, backups)?;
+    fs::rename(temp, path)
+}
+
+// Reads and parses a run in any format livesplit-core knows, along with the name of the format
+pub fn read_run(path: &Path) -> Result<(Run, String), FileResult> {
+    let file = fs::read(path).map_err(|e| FileResult::io(&e))?;
+    let parsed = composite::parse(&file, Some(path)).map_err(|e| FileResult::parse(e.to_string()))?;
+    Ok((parsed.run, parsed.kind.to_string()))
+}
+
+// Outcome of loading or saving a run, handed to godot as
+// { "ok": bool, "error": String, "message": String, "format": String }.
+// error is empty on success, otherwise one of "not_found", "permission_denied", "io", "parse" or "empty_run".
+// format is the timer the file was detected as coming from (LiveSplit, WSplit, Llanfair, ...), only set when loading.
+pub struct FileResult {
+    error: &'static str,
+    message: String,
+    format: String,
+}
+
+impl FileResult {
+    pub fn ok(format: String) -> Self {
+        Self {
+            error: "",
+            message: String::new(),
+            format,
+        }
+    }
+
+    pub fn io(e: &io::Error) -> Self {
+        let error = match e.kind() {
+            io::ErrorKind::NotFound => "not_found",
+            io::ErrorKind::PermissionDenied => "permission_denied",
+            _ => "io",
+        };
+        Self {
+            error,
+            message: e.to_string(),
+            format: String::new(),
+        }
+    }
+
+    pub fn parse(message: String) -> Self {
+        Self {
+            error: "parse",
+            message,
+            format: String::new(),
+        }
+    }
+
+    // Parsed, but with no segments the timer can't use it
+    pub fn empty_run(format: String) -> Self {
+        Self {
+            error: "empty_run",
+            message: String::from("The run has no segments"),
+            format,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.error.is_empty()
+    }
+
+    pub fn to_dict(&self) -> Dictionary {
+        let mut dict = Dictionary::new();
+        dict.set("ok", self.is_ok());
+        dict.set("error", self.error);
+        dict.set("message", self.message.as_str());
+        dict.set("format", self.format.as_str());
+        dict
+    }
+}
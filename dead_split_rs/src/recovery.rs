@@ -0,0 +1,454 @@
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use livesplit_core::{run::Attempt, AtomicDateTime, Run, Time, TimeSpan, Timer, TimerPhase, TimingMethod};
+
+use crate::segments;
+
+// Journals last written longer ago than this are too old to pick back up, 12 hours
+pub const MAX_JOURNAL_AGE: f64 = 12.0 * 60.0 * 60.0;
+
+// Journal of the attempt in progress, written next to the run every so often while the timer runs.
+// If the app dies mid-run, the attempt can be rebuilt from it on the next start.
+//
+// The file is plain text, one value per line:
+//   phase=1
+//   written_at=<unix seconds>
+//   started=<unix seconds>
+//   pause_time=<seconds or ->
+//   real_time=<seconds>
+//   game_time=<seconds or ->
+//   game_time_paused=true
+//   split=<real seconds or -> <game seconds or ->     (once per finished split)
+pub struct Journal {
+    phase: TimerPhase,
+    written_at: f64,
+    started: f64,
+    pause_time: Option<TimeSpan>,
+    real_time: TimeSpan,
+    game_time: Option<TimeSpan>,
+    game_time_paused: bool,
+    splits: Vec<Time>,
+}
+
+// What Journal::restore couldn't put back into the timer, applied to the run once the recovered attempt is reset
+pub struct Recovery {
+    original_offset: TimeSpan,
+    started: f64,
+    // Pause time from before the app closed
+    pause_time: Option<TimeSpan>,
+    splits: Vec<Time>,
+}
+
+// path.lss -> path.lss.attempt
+pub fn journal_path(run_path: &Path) -> PathBuf {
+    let mut name = OsString::from(run_path.as_os_str());
+    name.push(".attempt");
+    PathBuf::from(name)
+}
+
+fn unix_now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+fn format_time(t: Option<TimeSpan>) -> String {
+    match t {
+        Some(t) => t.total_seconds().to_string(),
+        None => String::from("-"),
+    }
+}
+
+fn parse_time(s: &str) -> Option<TimeSpan> {
+    s.parse::<f64>().ok().map(TimeSpan::from_seconds)
+}
+
+fn add_times(a: Option<TimeSpan>, b: Option<TimeSpan>) -> Option<TimeSpan> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a + b),
+        (a, b) => a.or(b),
+    }
+}
+
+impl Journal {
+    // None if there is no attempt to journal. For an attempt that was itself recovered, recovery fills in
+    // what the timer lost, so crashing twice doesn't lose more.
+    pub fn from_timer(timer: &Timer, recovery: Option<&Recovery>) -> Option<Self> {
+        let phase = timer.current_phase();
+        if phase == TimerPhase::NotRunning {
+            return None;
+        }
+        let split_index = timer.current_split_index()?;
+        let current_time = timer.snapshot().current_time();
+        let now = unix_now();
+        let mut splits: Vec<Time> = timer.run().segments()[..split_index]
+            .iter()
+            .map(|s| s.split_time())
+            .collect();
+        let (started, pause_time) = match recovery {
+            Some(recovery) => {
+                for (split, recovered) in splits.iter_mut().zip(&recovery.splits) {
+                    *split = *recovered;
+                }
+                (recovery.started, add_times(recovery.pause_time, timer.get_pause_time()))
+            }
+            None => (
+                now - timer.current_attempt_duration().total_seconds(),
+                timer.get_pause_time(),
+            ),
+        };
+        Some(Self {
+            phase,
+            written_at: now,
+            started,
+            pause_time,
+            real_time: current_time.real_time.unwrap_or_default(),
+            game_time: if timer.is_game_time_initialized() {
+                current_time.game_time
+            } else {
+                None
+            },
+            game_time_paused: timer.is_game_time_paused(),
+            splits,
+        })
+    }
+
+    pub fn parse(source: &str) -> Option<Self> {
+        let mut phase = None;
+        let mut written_at = None;
+        let mut started = None;
+        let mut pause_time = None;
+        let mut real_time = None;
+        let mut game_time = None;
+        let mut game_time_paused = false;
+        let mut splits = Vec::new();
+
+        for line in source.lines() {
+            let (key, value) = line.split_once('=')?;
+            match key {
+                "phase" => {
+                    phase = match value {
+                        "1" => Some(TimerPhase::Running),
+                        "2" => Some(TimerPhase::Ended),
+                        "3" => Some(TimerPhase::Paused),
+                        _ => return None,
+                    }
+                }
+                "written_at" => written_at = value.parse::<f64>().ok(),
+                "started" => started = value.parse::<f64>().ok(),
+                "pause_time" => pause_time = parse_time(value),
+                "real_time" => real_time = parse_time(value),
+                "game_time" => game_time = parse_time(value),
+                "game_time_paused" => game_time_paused = value == "true",
+                "split" => {
+                    let (real, game) = value.split_once(' ')?;
+                    splits.push(
+                        Time::new()
+                            .with_real_time(parse_time(real))
+                            .with_game_time(parse_time(game)),
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        let written_at = written_at?;
+        Some(Self {
+            phase: phase?,
+            written_at,
+            // Journals from before start times were journaled
+            started: started.unwrap_or(written_at),
+            pause_time,
+            real_time: real_time?,
+            game_time,
+            game_time_paused,
+            splits,
+        })
+    }
+
+    pub fn write(&self) -> String {
+        let mut out = format!(
+            "phase={}\nwritten_at={}\nstarted={}\npause_time={}\nreal_time={}\ngame_time={}\ngame_time_paused={}\n",
+            self.phase as u8,
+            self.written_at,
+            self.started,
+            format_time(self.pause_time),
+            self.real_time.total_seconds(),
+            format_time(self.game_time),
+            self.game_time_paused,
+        );
+        for split in &self.splits {
+            out.push_str(&format!(
+                "split={} {}\n",
+                format_time(split.real_time),
+                format_time(split.game_time)
+            ));
+        }
+        out
+    }
+
+    pub fn real_time(&self) -> TimeSpan {
+        self.real_time
+    }
+
+    // Seconds since the journal was written
+    pub fn age(&self) -> f64 {
+        (unix_now() - self.written_at).max(0.0)
+    }
+
+    // Whether this journal belongs to an attempt on the given timer's run and isn't too old to pick back up
+    pub fn fits(&self, timer: &Timer) -> bool {
+        self.splits.len() <= timer.run().len() && self.age() <= MAX_JOURNAL_AGE
+    }
+
+    // Puts the timer back where the journal left off. Time spent while the app was closed counts
+    // if the timer was running, same as it would have if the app had stayed open.
+    //
+    // livesplit-core can't move a running timer's start time, so the elapsed time is restored by
+    // starting the run with its offset set to the elapsed time. Finished splits can't be redone at
+    // their real times either, so they're skipped for now and their times are put into the run by
+    // Recovery::finish once the attempt is reset.
+    pub fn restore(&self, timer: &mut Timer) -> Option<Recovery> {
+        if timer.current_phase() != TimerPhase::NotRunning || !self.fits(timer) {
+            return None;
+        }
+
+        let downtime = match self.phase {
+            TimerPhase::Paused | TimerPhase::Ended => TimeSpan::zero(),
+            _ => TimeSpan::from_seconds(self.age()),
+        };
+
+        let original_offset = timer.run().offset();
+        let mut run = timer.run().clone();
+        run.set_offset(self.real_time + downtime);
+        timer.set_run(run).ok()?;
+        let _ = timer.start();
+
+        if self.game_time.is_some() {
+            let _ = timer.initialize_game_time();
+        }
+        for (idx, split) in self.splits.iter().enumerate() {
+            // The final split of a finished run is the restored time itself, so that one can be done for real
+            if self.phase == TimerPhase::Ended && idx + 1 == self.splits.len() {
+                if let Some(game_time) = split.game_time {
+                    timer.set_game_time(game_time);
+                }
+                let _ = timer.split();
+            } else {
+                let _ = timer.skip_split();
+            }
+        }
+        if let Some(game_time) = self.game_time {
+            if self.game_time_paused {
+                timer.pause_game_time();
+                timer.set_game_time(game_time);
+            } else {
+                timer.set_game_time(game_time + downtime);
+            }
+        }
+        if self.phase == TimerPhase::Paused {
+            let _ = timer.pause();
+        }
+
+        Some(Recovery {
+            original_offset,
+            started: self.started,
+            pause_time: self.pause_time,
+            splits: self.splits.clone(),
+        })
+    }
+}
+
+impl Recovery {
+    // Call on the run right after the recovered attempt was reset. Puts the run's offset back, and if the attempt
+    // was saved, gives it its real start and pause times and replaces the skipped splits in the segment history,
+    // golds and personal best with the journaled times.
+    //
+    // A discarded attempt never makes it into the attempt history, so then only the offset is put back.
+    pub fn finish(&self, run: &mut Run, saved: bool) {
+        run.set_offset(self.original_offset);
+        if !saved {
+            return;
+        }
+
+        let Some(id) = run.attempt_history().last().map(|a| a.index()) else {
+            return;
+        };
+        let mut started = AtomicDateTime::now();
+        started.time -= TimeSpan::from_seconds((unix_now() - self.started).max(0.0)).to_duration();
+        started.synced_with_atomic_clock = false;
+
+        segments::rebuild_attempt_history(run, |attempt| {
+            if attempt.index() != id {
+                return Some(attempt.clone());
+            }
+            Some(Attempt::new(
+                id,
+                attempt.time(),
+                Some(started),
+                attempt.ended(),
+                add_times(self.pause_time, attempt.pause_time()),
+            ))
+        });
+        self.patch_segments(run, id);
+    }
+
+    // Copy of the run with its own offset, for saving or editing it while the recovered attempt runs
+    pub fn original_run(&self, run: &Run) -> Run {
+        let mut run = run.clone();
+        run.set_offset(self.original_offset);
+        run
+    }
+
+    fn patch_segments(&self, run: &mut Run, id: i32) {
+        let recovered = self.splits.len().min(run.len());
+
+        for method in TimingMethod::all() {
+            // Segment times the same way livesplit-core works them out, from the last split that wasn't skipped
+            let mut last_split = TimeSpan::zero();
+            for (idx, split) in self.splits[..recovered].iter().enumerate() {
+                if let Some(time) = run.segment_mut(idx).segment_history_mut().get_mut(id) {
+                    time[method] = split[method].map(|t| t - last_split);
+                }
+                segments::improve_gold(run, idx, id, method);
+                if let Some(t) = split[method] {
+                    last_split = t;
+                }
+            }
+
+            // The first split done after recovering was timed from the start, it only covers the time since
+            // the last recovered split
+            for idx in recovered..run.len() {
+                let Some(time) = run.segment_mut(idx).segment_history_mut().get_mut(id) else {
+                    break;
+                };
+                if let Some(t) = time[method] {
+                    time[method] = Some(t - last_split);
+                    segments::improve_gold(run, idx, id, method);
+                    break;
+                }
+            }
+        }
+
+        // A new personal best got the skipped splits as its times too
+        let pb_set = run.segments()[..recovered]
+            .iter()
+            .all(|s| s.personal_best_split_time().real_time.is_none() && s.personal_best_split_time().game_time.is_none())
+            && run.attempt_history().last().is_some_and(|a| {
+                run.segments().last().map(|s| s.personal_best_split_time()) == Some(a.time())
+            });
+        if pb_set {
+            for (segment, split) in run.segments_mut().iter_mut().zip(&self.splits) {
+                *segment.personal_best_split_time_mut() = *split;
+            }
+        }
+        run.regenerate_comparisons();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{run_with_history, run_with_segments, seconds};
+
+    fn test_run() -> Run {
+        run_with_segments(&["One", "Two", "Three"])
+    }
+
+    fn journal() -> Journal {
+        Journal {
+            phase: TimerPhase::Paused,
+            written_at: unix_now(),
+            started: unix_now() - 300.0,
+            pause_time: seconds(5.0),
+            real_time: TimeSpan::from_seconds(250.0),
+            game_time: seconds(240.0),
+            game_time_paused: false,
+            splits: vec![
+                Time::new().with_real_time(seconds(100.0)).with_game_time(seconds(95.0)),
+                Time::new().with_real_time(seconds(200.0)).with_game_time(seconds(190.0)),
+            ],
+        }
+    }
+
+    #[test]
+    fn write_parse_round_trip() {
+        let original = journal();
+        let parsed = Journal::parse(&original.write()).expect("journal should parse");
+        assert_eq!(parsed.write(), original.write());
+        assert_eq!(parsed.splits, original.splits);
+        assert_eq!(parsed.pause_time, original.pause_time);
+        assert_eq!(parsed.started, original.started);
+    }
+
+    #[test]
+    fn stale_journal_does_not_fit() {
+        let timer = Timer::new(test_run()).unwrap();
+        let mut stale = journal();
+        stale.written_at -= MAX_JOURNAL_AGE + 1.0;
+        assert!(!stale.fits(&timer));
+        assert!(stale.restore(&mut Timer::new(test_run()).unwrap()).is_none());
+    }
+
+    #[test]
+    fn restore_and_finish_keep_journaled_times() {
+        let mut timer = Timer::new(test_run()).unwrap();
+        let recovery = journal().restore(&mut timer).expect("journal should restore");
+
+        assert_eq!(timer.current_phase(), TimerPhase::Paused);
+        assert_eq!(timer.current_split_index(), Some(2));
+        let current = timer.snapshot().current_time();
+        assert!((current.real_time.unwrap().total_seconds() - 250.0).abs() < 1.0);
+        // Replayed splits are skipped rather than given made up real times
+        assert!(timer.run().segment(0).split_time().real_time.is_none());
+
+        let _ = timer.resume();
+        let _ = timer.split();
+        let _ = timer.reset(true);
+        let mut run = timer.run().clone();
+        recovery.finish(&mut run, true);
+
+        assert_eq!(run.offset(), TimeSpan::zero());
+        let id = run.attempt_history().last().unwrap().index();
+        let first = run.segment(0).segment_history().get(id).unwrap();
+        let second = run.segment(1).segment_history().get(id).unwrap();
+        let third = run.segment(2).segment_history().get(id).unwrap();
+        assert_eq!(first.real_time, seconds(100.0));
+        assert_eq!(second.real_time, seconds(100.0));
+        assert_eq!(second.game_time, seconds(95.0));
+        assert!((third.real_time.unwrap().total_seconds() - 50.0).abs() < 1.0);
+        assert_eq!(run.segment(0).best_segment_time().real_time, seconds(100.0));
+        assert_eq!(run.segment(1).personal_best_split_time().real_time, seconds(200.0));
+
+        let attempt = run.attempt_history().last().unwrap();
+        assert_eq!(attempt.pause_time().map(|t| t.total_seconds() >= 5.0), Some(true));
+        let started = attempt.started().unwrap().time.unix_timestamp() as f64;
+        assert!((started - (unix_now() - 300.0)).abs() < 5.0);
+    }
+
+    #[test]
+    fn discarded_attempt_only_gets_its_offset_back() {
+        // An older attempt that reset before the first split
+        let mut run = run_with_history(&["One", "Two", "Three"], &[&[]]);
+        run.set_offset(TimeSpan::from_seconds(-3.0));
+        let mut timer = Timer::new(run).unwrap();
+        let recovery = journal().restore(&mut timer).expect("journal should restore");
+        assert_eq!(recovery.original_run(timer.run()).offset(), TimeSpan::from_seconds(-3.0));
+
+        let _ = timer.reset(false);
+        let mut run = timer.run().clone();
+        recovery.finish(&mut run, false);
+
+        assert_eq!(run.offset(), TimeSpan::from_seconds(-3.0));
+        // The older attempt is left alone
+        let attempts = run.attempt_history();
+        assert_eq!(attempts.len(), 1);
+        assert!(attempts[0].started().is_none());
+        assert!(attempts[0].pause_time().is_none());
+    }
+}
@@ -0,0 +1,70 @@
+use livesplit_core::{Run, TimingMethod};
+
+// Settings that belong to a run rather than the app, like which extra comparisons it generates.
+// The .lss format has no place for them, so they're kept as permanent custom variables, which
+// livesplit-core saves with the run. They start with PREFIX and are left out of the metadata shown in godot.
+pub const PREFIX: &str = "DeadSplit ";
+
+pub const COMPARISON_GENERATORS: &str = "DeadSplit Comparison Generators";
+pub const TIMING_METHOD: &str = "DeadSplit Timing Method";
+// Followed by the comparison's name, one per goal comparison
+pub const GOAL: &str = "DeadSplit Goal ";
+
+pub fn is_setting(name: &str) -> bool {
+    name.starts_with(PREFIX)
+}
+
+pub fn get<'a>(run: &'a Run, key: &str) -> Option<&'a str> {
+    run.metadata()
+        .custom_variable(key)
+        .map(|variable| variable.value.as_str())
+}
+
+// Every setting whose key starts with prefix, with the prefix cut off
+pub fn with_prefix(run: &Run, prefix: &str) -> Vec<(String, String)> {
+    run.metadata()
+        .custom_variables()
+        .filter_map(|(name, variable)| {
+            let rest = name.strip_prefix(prefix)?;
+            Some((rest.to_owned(), variable.value.clone()))
+        })
+        .collect()
+}
+
+// An empty value removes the setting
+pub fn set(run: &mut Run, key: &str, value: String) {
+    if value.is_empty() {
+        run.metadata_mut().remove_custom_variable(key);
+        return;
+    }
+    let variable = run.metadata_mut().custom_variable_mut(key);
+    variable.value = value;
+    variable.is_permanent = true;
+}
+
+// Timing methods go to godot as ints, 0 for real time and 1 for game time
+pub fn timing_method_from_int(method: i32) -> Option<TimingMethod> {
+    match method {
+        0 => Some(TimingMethod::RealTime),
+        1 => Some(TimingMethod::GameTime),
+        _ => None,
+    }
+}
+
+// The timing method the timer switches to when the run is loaded, None if the run doesn't have one
+pub fn timing_method(run: &Run) -> Option<TimingMethod> {
+    match get(run, TIMING_METHOD)? {
+        "real_time" => Some(TimingMethod::RealTime),
+        "game_time" => Some(TimingMethod::GameTime),
+        _ => None,
+    }
+}
+
+pub fn set_timing_method(run: &mut Run, method: Option<TimingMethod>) {
+    let value = match method {
+        Some(TimingMethod::RealTime) => "real_time",
+        Some(TimingMethod::GameTime) => "game_time",
+        None => "",
+    };
+    set(run, TIMING_METHOD, value.to_owned());
+}
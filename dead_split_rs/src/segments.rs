@@ -0,0 +1,276 @@
+use livesplit_core::{run::Attempt, Run, Segment, SegmentHistory, Time, TimeSpan, TimingMethod};
+
+fn scale(t: Option<TimeSpan>, ratio: f64) -> Option<TimeSpan> {
+    t.map(|t| TimeSpan::from_seconds(t.total_seconds() * ratio))
+}
+
+// A segment time right after a skipped split covers more than one segment, so it can't be a gold
+pub fn follows_skip(run: &Run, idx: usize, attempt_id: i32, method: TimingMethod) -> bool {
+    idx > 0
+        && run.segment(idx - 1)
+            .segment_history()
+            .get(attempt_id)
+            .and_then(|t| t[method])
+            .is_none()
+}
+
+// Lowers the gold of segment idx to the attempt's time for it in the history, if that's faster and can be a gold
+pub fn improve_gold(run: &mut Run, idx: usize, attempt_id: i32, method: TimingMethod) {
+    if follows_skip(run, idx, attempt_id, method) {
+        return;
+    }
+    let Some(time) = run.segment(idx).segment_history().get(attempt_id).and_then(|t| t[method]) else {
+        return;
+    };
+    let best = &mut run.segment_mut(idx).best_segment_time_mut()[method];
+    if best.is_none_or(|best| time < best) {
+        *best = Some(time);
+    }
+}
+
+// Best time for segment idx in its history that can be a gold
+pub fn best_from_history(run: &Run, idx: usize, method: TimingMethod) -> Option<TimeSpan> {
+    run.segment(idx)
+        .segment_history()
+        .iter()
+        .filter(|&&(attempt_id, _)| !follows_skip(run, idx, attempt_id, method))
+        .filter_map(|(_, t)| t[method])
+        .min()
+}
+
+// The attempt history can only be cleared as a whole, together with the segment histories, so changing an
+// attempt means building both again. edit gives each attempt's replacement, or None to remove it along with
+// its segment times.
+pub fn rebuild_attempt_history(run: &mut Run, mut edit: impl FnMut(&Attempt) -> Option<Attempt>) {
+    let attempts: Vec<Attempt> = run.attempt_history().to_vec();
+    let histories: Vec<SegmentHistory> = run
+        .segments()
+        .iter()
+        .map(|s| s.segment_history().clone())
+        .collect();
+    run.clear_history();
+    let mut removed = Vec::new();
+    for attempt in &attempts {
+        match edit(attempt) {
+            Some(a) => run.add_attempt_with_index(a.time(), a.index(), a.started(), a.ended(), a.pause_time()),
+            None => removed.push(attempt.index()),
+        }
+    }
+    for (segment, mut history) in run.segments_mut().iter_mut().zip(histories) {
+        for &attempt_id in &removed {
+            history.remove(attempt_id);
+        }
+        *segment.segment_history_mut() = history;
+    }
+}
+
+// Merges segment idx into the one after it, which keeps its split times, icon and (unless a name is given) name.
+// History entries are added up per attempt. A skipped first half is already counted in the second half's time,
+// and attempts that reset in the second half never finished the merged segment, so they're left out. When only
+// the second half was skipped, the first half's time is added to the next segment that attempt split.
+// Golds become the best merged time from the history (see best_from_history), or the two golds added up if
+// there's none.
+pub fn merge(run: &mut Run, idx: usize, name: &str) -> bool {
+    if idx + 1 >= run.len() {
+        return false;
+    }
+    let first = run.segments_mut().remove(idx);
+    let second = run.segment_mut(idx);
+
+    let mut history = SegmentHistory::default();
+    // First half times of attempts that skipped the second half's split, which move on to the next segment they split
+    let mut carried = Vec::new();
+    for &(attempt_id, second_time) in second.segment_history().iter() {
+        let first_time = first.segment_history().get(attempt_id).unwrap_or_default();
+        let mut time = Time::new();
+        for method in TimingMethod::all() {
+            time[method] = match (first_time[method], second_time[method]) {
+                (Some(a), Some(b)) => Some(a + b),
+                (None, b) => b,
+                (Some(a), None) => {
+                    carried.push((attempt_id, method, a));
+                    None
+                }
+            };
+        }
+        history.insert(attempt_id, time);
+    }
+
+    *second.segment_history_mut() = history;
+    if !name.is_empty() {
+        second.set_name(name);
+    }
+    for method in TimingMethod::all() {
+        let golds = first.best_segment_time()[method]
+            .zip(run.segment(idx).best_segment_time()[method])
+            .map(|(a, b)| a + b);
+        let best = best_from_history(run, idx, method).or(golds);
+        run.segment_mut(idx).best_segment_time_mut()[method] = best;
+    }
+
+    // Attempts that reset before splitting again simply lose the time, like they would have in livesplit
+    for (attempt_id, method, carry) in carried {
+        for segment in &mut run.segments_mut()[idx + 1..] {
+            let Some(time) = segment.segment_history_mut().get_mut(attempt_id) else {
+                break;
+            };
+            if let Some(t) = &mut time[method] {
+                *t += carry;
+                break;
+            }
+        }
+    }
+
+    run.regenerate_comparisons();
+    true
+}
+
+// Splits segment idx in two by inserting a new segment named name before it. With a ratio between 0 and 1
+// the new segment takes that share of every history entry, gold and comparison segment, and the old one keeps
+// the rest. With a ratio of 0 the new segment starts out empty, like inserting one in the editor.
+pub fn split(run: &mut Run, idx: usize, name: &str, ratio: f64) -> bool {
+    if idx >= run.len() || !(0.0..1.0).contains(&ratio) {
+        return false;
+    }
+    let mut new_segment = Segment::new(name);
+    if ratio == 0.0 {
+        run.segments_mut().insert(idx, new_segment);
+        run.regenerate_comparisons();
+        return true;
+    }
+
+    let comparisons: Vec<String> = run.custom_comparisons().to_vec();
+    for comparison in &comparisons {
+        for method in TimingMethod::all() {
+            let start = match idx {
+                0 => Some(TimeSpan::zero()),
+                _ => run.segment(idx - 1).comparison(comparison)[method],
+            };
+            let end = run.segment(idx).comparison(comparison)[method];
+            new_segment.comparison_mut(comparison)[method] = start
+                .zip(end)
+                .map(|(start, end)| start + TimeSpan::from_seconds((end - start).total_seconds() * ratio));
+        }
+    }
+
+    let old_segment = run.segment_mut(idx);
+    let mut first_history = SegmentHistory::default();
+    let mut second_history = SegmentHistory::default();
+    for &(attempt_id, time) in old_segment.segment_history().iter() {
+        let mut first = Time::new();
+        let mut second = Time::new();
+        for method in TimingMethod::all() {
+            first[method] = scale(time[method], ratio);
+            second[method] = scale(time[method], 1.0 - ratio);
+        }
+        first_history.insert(attempt_id, first);
+        second_history.insert(attempt_id, second);
+    }
+    for method in TimingMethod::all() {
+        let best = old_segment.best_segment_time()[method];
+        new_segment.best_segment_time_mut()[method] = scale(best, ratio);
+        old_segment.best_segment_time_mut()[method] = scale(best, 1.0 - ratio);
+    }
+    *new_segment.segment_history_mut() = first_history;
+    *old_segment.segment_history_mut() = second_history;
+
+    run.segments_mut().insert(idx, new_segment);
+    run.regenerate_comparisons();
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{history, run_with_history, seconds};
+
+    #[test]
+    fn merge_adds_up_history() {
+        let mut run = run_with_history(
+            &["One", "Two", "Three"],
+            &[&[Some(10.0), Some(20.0), Some(30.0)], &[None, Some(25.0), Some(30.0)], &[Some(12.0)]],
+        );
+        assert!(merge(&mut run, 0, "One and Two"));
+
+        assert_eq!(run.len(), 2);
+        assert_eq!(run.segment(0).name(), "One and Two");
+        assert_eq!(history(&run, 0, 1), Some(seconds(30.0)));
+        // The skipped first half is already in the second half's time
+        assert_eq!(history(&run, 0, 2), Some(seconds(25.0)));
+        // Reset before finishing the merged segment
+        assert_eq!(history(&run, 0, 3), None);
+        assert_eq!(run.segment(0).best_segment_time().real_time, seconds(25.0));
+    }
+
+    #[test]
+    fn merged_gold_leaves_out_times_after_a_skip() {
+        let mut run = run_with_history(
+            &["One", "Two", "Three"],
+            &[&[None, Some(5.0), Some(10.0)], &[Some(10.0), Some(10.0), Some(10.0)]],
+        );
+        assert!(merge(&mut run, 1, ""));
+
+        // Attempt 1's 15 also covers the skipped first segment
+        assert_eq!(history(&run, 1, 1), Some(seconds(15.0)));
+        assert_eq!(run.segment(1).best_segment_time().real_time, seconds(20.0));
+    }
+
+    #[test]
+    fn merge_carries_first_half_past_skipped_split() {
+        let mut run = run_with_history(
+            &["One", "Two", "Three", "Four"],
+            &[&[Some(10.0), None, None, Some(40.0)], &[Some(10.0), None]],
+        );
+        assert!(merge(&mut run, 0, ""));
+
+        assert_eq!(run.segment(0).name(), "Two");
+        assert_eq!(history(&run, 0, 1), Some(None));
+        assert_eq!(history(&run, 1, 1), Some(None));
+        assert_eq!(history(&run, 2, 1), Some(seconds(50.0)));
+        // Reset without splitting again, there's nowhere to put the time
+        assert_eq!(history(&run, 0, 2), Some(None));
+        assert_eq!(history(&run, 1, 2), None);
+    }
+
+    #[test]
+    fn merge_needs_a_next_segment() {
+        let mut run = run_with_history(&["One", "Two"], &[]);
+        assert!(!merge(&mut run, 1, ""));
+        assert_eq!(run.len(), 2);
+    }
+
+    #[test]
+    fn split_shares_out_history_and_golds() {
+        let mut run = run_with_history(&["One", "Two"], &[&[Some(10.0), Some(40.0)], &[Some(10.0), None]]);
+        run.segment_mut(1).best_segment_time_mut().real_time = seconds(40.0);
+        assert!(split(&mut run, 1, "Half", 0.25));
+
+        assert_eq!(run.len(), 3);
+        assert_eq!(run.segment(1).name(), "Half");
+        assert_eq!(history(&run, 1, 1), Some(seconds(10.0)));
+        assert_eq!(history(&run, 2, 1), Some(seconds(30.0)));
+        assert_eq!(history(&run, 1, 2), Some(None));
+        assert_eq!(history(&run, 2, 2), Some(None));
+        assert_eq!(run.segment(1).best_segment_time().real_time, seconds(10.0));
+        assert_eq!(run.segment(2).best_segment_time().real_time, seconds(30.0));
+    }
+
+    #[test]
+    fn split_with_no_ratio_inserts_an_empty_segment() {
+        let mut run = run_with_history(&["One"], &[&[Some(10.0)]]);
+        assert!(split(&mut run, 0, "New", 0.0));
+
+        assert_eq!(run.segment(0).name(), "New");
+        assert_eq!(history(&run, 0, 1), None);
+        assert_eq!(history(&run, 1, 1), Some(seconds(10.0)));
+        assert!(!split(&mut run, 0, "Bad", 1.0));
+        assert!(!split(&mut run, 5, "Bad", 0.5));
+    }
+
+    #[test]
+    fn best_from_history_ignores_times_after_a_skip() {
+        let run = run_with_history(&["One", "Two"], &[&[None, Some(15.0)], &[Some(10.0), Some(20.0)]]);
+        assert_eq!(best_from_history(&run, 1, TimingMethod::RealTime), seconds(20.0));
+        assert_eq!(best_from_history(&run, 0, TimingMethod::RealTime), seconds(10.0));
+    }
+}
@@ -0,0 +1,85 @@
+// Subsplits follow livesplit's naming convention: every segment in a group but the last starts with "-",
+// and the last one is named "{Group Name} Segment Name".
+
+pub enum SplitNode {
+    Single(usize),
+    Group { name: String, indices: Vec<usize> },
+}
+
+pub fn is_subsplit(name: &str) -> bool {
+    name.starts_with('-')
+}
+
+// Name of the group if this segment ends one
+pub fn group_name(name: &str) -> Option<&str> {
+    let rest = name.strip_prefix('{')?;
+    rest.find('}').map(|end| &rest[..end])
+}
+
+// The segment's own name, without any subsplit markers
+pub fn base_name(name: &str) -> &str {
+    if let Some(rest) = name.strip_prefix('-') {
+        return rest;
+    }
+    if group_name(name).is_some() {
+        return name[name.find('}').unwrap_or_default() + 1..].trim_start();
+    }
+    name
+}
+
+pub fn in_group(name: &str) -> bool {
+    is_subsplit(name) || group_name(name).is_some()
+}
+
+pub fn group_member_name(base: &str) -> String {
+    format!("-{base}")
+}
+
+pub fn group_end_name(group: &str, base: &str) -> String {
+    format!("{{{group}}} {base}")
+}
+
+// Groups up the segments. "-" segments left open at the end of the run make a group with no name.
+pub fn parse_tree<'a>(names: impl IntoIterator<Item = &'a str>) -> Vec<SplitNode> {
+    let mut nodes = Vec::new();
+    let mut open: Vec<usize> = Vec::new();
+
+    for (idx, name) in names.into_iter().enumerate() {
+        if is_subsplit(name) {
+            open.push(idx);
+        } else if let Some(group) = group_name(name) {
+            open.push(idx);
+            nodes.push(SplitNode::Group {
+                name: group.to_owned(),
+                indices: std::mem::take(&mut open),
+            });
+        } else {
+            // A plain split right after "-" segments closes them off as a nameless group
+            if !open.is_empty() {
+                nodes.push(SplitNode::Group {
+                    name: String::new(),
+                    indices: std::mem::take(&mut open),
+                });
+            }
+            nodes.push(SplitNode::Single(idx));
+        }
+    }
+    if !open.is_empty() {
+        nodes.push(SplitNode::Group {
+            name: String::new(),
+            indices: open,
+        });
+    }
+
+    nodes
+}
+
+// Index range of the group containing idx, or None if it's a plain split
+pub fn group_range<'a>(names: impl IntoIterator<Item = &'a str>, idx: usize) -> Option<(usize, usize)> {
+    parse_tree(names).into_iter().find_map(|node| match node {
+        SplitNode::Group { indices, .. } if indices.contains(&idx) => {
+            Some((indices[0], *indices.last()?))
+        }
+        _ => None,
+    })
+}
@@ -0,0 +1,46 @@
+// Runs and times for the unit tests
+use livesplit_core::{AtomicDateTime, Run, Segment, Time, TimeSpan};
+
+pub fn seconds(t: f64) -> Option<TimeSpan> {
+    Some(TimeSpan::from_seconds(t))
+}
+
+pub fn started(minutes_ago: f64) -> AtomicDateTime {
+    let mut started = AtomicDateTime::now();
+    started.time -= TimeSpan::from_seconds(minutes_ago * 60.0).to_duration();
+    started
+}
+
+pub fn run_with_segments(names: &[&str]) -> Run {
+    let mut run = Run::new();
+    for name in names {
+        run.push_segment(Segment::new(*name));
+    }
+    run
+}
+
+// Attempts are (id, start time, real time segment times), None being a skipped split and a shorter list
+// an attempt that reset before reaching the rest of the segments
+pub fn run_with_attempts(names: &[&str], attempts: &[(i32, Option<AtomicDateTime>, &[Option<f64>])]) -> Run {
+    let mut run = run_with_segments(names);
+    for &(id, started, times) in attempts {
+        run.add_attempt_with_index(Time::new(), id, started, None, None);
+        for (segment, time) in run.segments_mut().iter_mut().zip(times) {
+            segment.segment_history_mut().insert(id, Time::new().with_real_time(time.and_then(seconds)));
+        }
+    }
+    run.set_attempt_count(attempts.len() as u32);
+    run
+}
+
+// Same as run_with_attempts, with the attempts numbered from 1 and no start times
+pub fn run_with_history(names: &[&str], attempts: &[&[Option<f64>]]) -> Run {
+    let attempts: Vec<(i32, Option<AtomicDateTime>, &[Option<f64>])> =
+        (1..).zip(attempts).map(|(id, &times)| (id, None, times)).collect();
+    run_with_attempts(names, &attempts)
+}
+
+// None if the attempt has no entry for the segment, Some(None) if it skipped the split
+pub fn history(run: &Run, idx: usize, attempt_id: i32) -> Option<Option<TimeSpan>> {
+    run.segment(idx).segment_history().get(attempt_id).map(|t| t.real_time)
+}
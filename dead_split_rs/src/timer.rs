@@ -1,18 +1,24 @@
 use std::{
     ffi::OsStr,
-    fs::{self, File},
-    io::BufWriter,
-    path::Path,
+    fs,
+    path::{Path, PathBuf},
 };
 
-use crate::editable_run::EditableRun;
-use godot::prelude::*;
+use crate::{
+    editable_run::EditableRun,
+    events, export, generators, icons, layout, lsl,
+    persistence::{self, FileResult},
+    recovery::{self, Journal},
+    run_settings,
+};
+use godot::{classes::ImageTexture, prelude::*};
 use livesplit_core::{
-    analysis::total_playtime::TotalPlaytime,
-    run::{
-        parser::composite,
-        saver::livesplit::{self, IoWrite},
+    TimeSpan, TimerPhase, TimingMethod,
+    analysis::{
+        current_pace, delta, pb_chance, possible_time_save, sum_of_segments,
+        total_playtime::TotalPlaytime,
     },
+    comparison::best_segments,
 };
 
 use read_process_memory::*;
@@ -24,62 +30,114 @@ use super::*;
 impl DeadSplitTimer {
     // Timer control
     #[func]
-    fn new_run(&self) {
+    fn new_run(&mut self) {
+        self.drop_attempt();
         let mut binding = timer_write(&self.timer);
         let _ = binding.replace_run(get_default_run(), true);
+        self.run_path = None;
+        self.revision += 1;
     }
 
     #[func]
     fn start_split(&self) {
-        let mut binding = timer_write(&self.timer);
-        let _ = binding.split_or_start();
+        events::act(&self.timer, &self.events, false, |timer| {
+            let _ = timer.split_or_start();
+        });
     }
 
+    // Resets and saves the attempt's times
     #[func]
-    fn reset(&self) {
-        let mut binding = timer_write(&self.timer);
-        let current_split_index = binding.current_split_index().unwrap_or_default();
-        let _ = binding.reset(true);
-        let mut run = binding.run().clone();
-        run.update_segment_history(current_split_index);
-        run.fix_splits();
-        let _ = binding.replace_run(run, true); // WHY WOULD YOU MAKE THIS A RESULT ISTG
+    fn reset(&mut self) {
+        self.reset_attempt(true);
+    }
+
+    // With update_splits false the attempt is thrown away completely: it isn't added to the attempt history
+    // and its segment times, golds and personal best aren't kept
+    #[func]
+    fn reset_attempt(&mut self, update_splits: bool) {
+        // livesplit-core's reset already updates the segment history when the splits are kept
+        events::act(&self.timer, &self.events, update_splits, |timer| {
+            let _ = timer.reset(update_splits);
+        });
+        self.revision += 1;
+    }
+
+    // Whether resetting now would change any golds or the personal best,
+    // i.e. whether the UI should ask before saving the attempt
+    #[func]
+    fn has_new_best_times(&self) -> bool {
+        let binding = timer_read(&self.timer);
+        binding.current_attempt_has_new_best_times()
+    }
+
+    #[func]
+    fn has_new_personal_best(&self) -> bool {
+        let binding = timer_read(&self.timer);
+        binding.current_attempt_has_new_personal_best(binding.current_timing_method())
     }
 
     #[func]
     fn pause(&self) {
-        let mut binding = timer_write(&self.timer);
-        let _ = binding.pause();
+        events::act(&self.timer, &self.events, false, |timer| {
+            let _ = timer.pause();
+        });
     }
 
     #[func]
     fn resume(&self) {
-        let mut binding = timer_write(&self.timer);
-        let _ = binding.resume();
+        events::act(&self.timer, &self.events, false, |timer| {
+            let _ = timer.resume();
+        });
     }
 
     #[func]
     fn toggle_pause(&self) {
-        let mut binding = timer_write(&self.timer);
-        let _ = binding.toggle_pause();
+        events::act(&self.timer, &self.events, false, |timer| {
+            let _ = timer.toggle_pause();
+        });
     }
 
     #[func]
     fn undo_all_pauses(&self) {
-        let mut binding = timer_write(&self.timer);
-        let _ = binding.undo_all_pauses();
+        events::act(&self.timer, &self.events, false, |timer| {
+            let _ = timer.undo_all_pauses();
+        });
     }
 
     #[func]
     fn skip_split(&self) {
-        let mut binding = timer_write(&self.timer);
-        let _ = binding.skip_split();
+        events::act(&self.timer, &self.events, false, |timer| {
+            let _ = timer.skip_split();
+        });
     }
 
     #[func]
     fn undo_split(&self) {
-        let mut binding = timer_write(&self.timer);
-        let _ = binding.undo_split();
+        events::act(&self.timer, &self.events, false, |timer| {
+            let _ = timer.undo_split();
+        });
+    }
+
+    // 0 for real time, 1 for game time
+    #[func]
+    fn get_timing_method(&self) -> i32 {
+        let binding = timer_read(&self.timer);
+        binding.current_timing_method() as i32
+    }
+
+    #[func]
+    fn set_timing_method(&self, method: i32) {
+        if let Some(method) = run_settings::timing_method_from_int(method) {
+            let mut binding = timer_write(&self.timer);
+            binding.set_current_timing_method(method);
+        }
+    }
+
+    // The timing method saved with the run, -1 if it doesn't have one. Set it with EditableRun.set_default_timing_method
+    #[func]
+    fn get_default_timing_method(&self) -> i32 {
+        let binding = timer_read(&self.timer);
+        run_settings::timing_method(binding.run()).map_or(-1, |m| m as i32)
     }
 
     #[func]
@@ -89,36 +147,112 @@ impl DeadSplitTimer {
     }
 
     #[func]
-    fn try_save_run(&self, file_path: String) -> bool {
-        let binding = timer_read(&self.timer);
-        let writer = match File::create(file_path) {
-            Ok(f) => BufWriter::new(f),
-            Err(_) => return false,
-        };
+    fn try_save_run(&mut self, file_path: String) -> bool {
+        self.save_run_file(file_path).is_ok()
+    }
 
-        match livesplit::save_run(binding.run(), IoWrite(writer)) {
-            Ok(_) => true,
-            Err(_) => false,
-        }
+    #[func]
+    fn try_load_run(&mut self, file_path: String) -> bool {
+        self.load_run_file(file_path).is_ok()
     }
 
+    // Same as try_save_run, but says what went wrong. See persistence::FileResult for the dictionary's keys
     #[func]
-    fn try_load_run(&self, file_path: String) -> bool {
-        let mut binding = timer_write(&self.timer);
+    fn save_run(&mut self, file_path: String) -> Dictionary {
+        self.save_run_file(file_path).to_dict()
+    }
+
+    // Same as try_load_run, but says what went wrong and which timer the file came from
+    #[func]
+    fn load_run(&mut self, file_path: String) -> Dictionary {
+        self.load_run_file(file_path).to_dict()
+    }
+
+    fn save_run_file(&mut self, file_path: String) -> FileResult {
+        let path = PathBuf::from(file_path);
+        // Backups are only kept by autosaves, an explicit save just replaces the file
+        if let Err(e) = persistence::save_run_atomic(&self.clean_run(), &path, 0) {
+            return FileResult::io(&e);
+        }
+        self.run_path = Some(path);
+        FileResult::ok(String::from("LiveSplit"))
+    }
+
+    fn load_run_file(&mut self, file_path: String) -> FileResult {
         let path = Path::new(&file_path);
-        let file = match fs::read(path) {
-            Ok(f) => f,
-            Err(_) => return false,
+        let (mut run, format) = match persistence::read_run(path) {
+            Ok(parsed) => parsed,
+            Err(result) => return result,
+        };
+        generators::apply(&mut run);
+        if run.is_empty() {
+            return FileResult::empty_run(format);
+        }
+        self.drop_attempt();
+        let mut binding = timer_write(&self.timer);
+        if binding.replace_run(run, true).is_err() {
+            return FileResult::empty_run(format);
+        }
+        drop(binding);
+        self.apply_run_timing_method();
+        // Autosaves write LiveSplit's format, so runs from other timers aren't overwritten in place.
+        // They're only kept once saved somewhere as a .lss.
+        self.run_path = (format == "LiveSplit").then(|| path.to_path_buf());
+        self.revision += 1;
+        FileResult::ok(format)
+    }
+
+    fn read_journal(&self) -> Option<Journal> {
+        let path = recovery::journal_path(self.run_path.as_ref()?);
+        Journal::parse(&fs::read_to_string(path).ok()?)
+    }
+
+    // Path the run was last loaded from or saved to, where autosaves go. Empty for a new run, or one loaded
+    // from another timer's format until it's saved.
+    #[func]
+    fn get_run_path(&self) -> String {
+        self.run_path
+            .as_ref()
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    // Crash recovery. An attempt in progress is journaled next to the run file,
+    // and if the app closes before the attempt is reset it can be picked back up.
+    // Journals older than recovery::MAX_JOURNAL_AGE aren't offered.
+    #[func]
+    fn has_recoverable_attempt(&self) -> bool {
+        let binding = timer_read(&self.timer);
+        binding.current_phase() == TimerPhase::NotRunning
+            && self.read_journal().is_some_and(|j| j.fits(&binding))
+    }
+
+    // Timer's time when the journal was written, so the user can be asked whether to pick it back up. -1 if there's none.
+    #[func]
+    fn get_recoverable_attempt_time(&self) -> f64 {
+        self.read_journal()
+            .map_or(-1.0, |j| j.real_time().total_seconds())
+    }
+
+    #[func]
+    fn recover_attempt(&mut self) -> bool {
+        let Some(journal) = self.read_journal() else {
+            return false;
         };
+        let mut binding = timer_write(&self.timer);
+        match journal.restore(&mut binding) {
+            Some(recovery) => {
+                self.recovery = Some(recovery);
+                self.revision += 1;
+                true
+            }
+            None => false,
+        }
+    }
 
-        let _ = binding.replace_run(
-            match composite::parse(&file, Some(path)) {
-                Ok(p) => p.run,
-                Err(_) => return false,
-            },
-            true,
-        );
-        true
+    #[func]
+    fn discard_recoverable_attempt(&self) {
+        self.discard_journal();
     }
 
     #[func]
@@ -146,11 +280,12 @@ impl DeadSplitTimer {
     }
 
     #[func]
-    fn regenerate_comparisons(&self) {
+    fn regenerate_comparisons(&mut self) {
         let mut binding = timer_write(&self.timer);
         let mut new_run = binding.run().clone();
         new_run.regenerate_comparisons();
         let _ = binding.set_run(new_run);
+        self.revision += 1;
     }
 
     // Get timer data
@@ -166,13 +301,19 @@ impl DeadSplitTimer {
         binding.run().segment(idx as usize).name().to_owned()
     }
 
+    // An empty comparison name uses the timer's current comparison
     #[func]
     fn get_segment_comparison(&self, idx: i32, comparing_to: String, rta: bool) -> f64 {
         let binding = timer_read(&self.timer);
+        let comparing_to = if comparing_to.is_empty() {
+            binding.current_comparison()
+        } else {
+            comparing_to.as_str()
+        };
         let comp = binding
             .run()
             .segment(idx as usize)
-            .comparison(&comparing_to);
+            .comparison(comparing_to);
         if rta {
             comp.real_time.unwrap_or_default().total_seconds()
         } else {
@@ -216,6 +357,19 @@ impl DeadSplitTimer {
         }
     }
 
+    // Same as the EditableRun icon getters, for showing icons while the timer runs
+    #[func]
+    fn get_segment_icon_texture(&self, idx: i32) -> Option<Gd<ImageTexture>> {
+        let binding = timer_read(&self.timer);
+        icons::texture_from_bytes(binding.run().segment(idx as usize).icon().data())
+    }
+
+    #[func]
+    fn get_game_icon_texture(&self) -> Option<Gd<ImageTexture>> {
+        let binding = timer_read(&self.timer);
+        icons::texture_from_bytes(binding.run().game_icon().data())
+    }
+
     #[func]
     fn get_total_playtime(&self) -> f64 {
         let binding = timer_read(&self.timer);
@@ -234,6 +388,20 @@ impl DeadSplitTimer {
         binding.run().category_name().to_owned()
     }
 
+    // For the title area, see export::metadata_dict for the keys
+    #[func]
+    fn get_metadata(&self) -> Dictionary {
+        let binding = timer_read(&self.timer);
+        export::metadata_dict(binding.run().metadata())
+    }
+
+    // Seconds the timer counts down from after starting, set with EditableRun.set_start_delay
+    #[func]
+    fn get_start_delay(&self) -> f64 {
+        let binding = timer_read(&self.timer);
+        (-binding.run().offset().total_seconds()).max(0.0)
+    }
+
     #[func]
     fn get_attempt_count(&self) -> i32 {
         let binding = timer_read(&self.timer);
@@ -255,14 +423,16 @@ impl DeadSplitTimer {
     // run interfacing
     #[func]
     fn update_run(&mut self, editable_run: Gd<EditableRun>) {
+        self.drop_attempt();
         let mut binding = timer_write(&self.timer);
         let _ = binding.replace_run(editable_run.bind().get_run(), true);
+        drop(binding);
+        self.revision += 1;
     }
 
     #[func]
     fn get_editable_run(&self) -> Gd<EditableRun> {
-        let binding = timer_read(&self.timer);
-        EditableRun::from_run(binding.run())
+        EditableRun::from_run(&self.clean_run())
     }
 
     #[func]
@@ -271,6 +441,274 @@ impl DeadSplitTimer {
         Array::from_iter(binding.run().comparisons().map(|s| GString::from(s)))
     }
 
+    // Comparisons
+    #[func]
+    fn get_current_comparison(&self) -> String {
+        let binding = timer_read(&self.timer);
+        binding.current_comparison().to_owned()
+    }
+
+    // Fails if the run has no comparison with this name
+    #[func]
+    fn set_current_comparison(&self, comparison: String) -> bool {
+        let mut binding = timer_write(&self.timer);
+        binding.set_current_comparison(comparison).is_ok()
+    }
+
+    #[func]
+    fn switch_to_next_comparison(&self) {
+        let mut binding = timer_write(&self.timer);
+        binding.switch_to_next_comparison();
+    }
+
+    #[func]
+    fn switch_to_previous_comparison(&self) {
+        let mut binding = timer_write(&self.timer);
+        binding.switch_to_previous_comparison();
+    }
+
+    // Emitted from process, so switches made by the autosplitter thread are picked up too
+    #[signal]
+    pub fn comparison_changed(&mut self, comparison: GString);
+
+    // Timer events, emitted from process no matter whether the action came from godot or the autosplitter.
+    // The reset signal is run_reset so it doesn't share a name with the reset func.
+    #[signal]
+    pub fn started(&mut self);
+
+    #[signal]
+    pub fn split(&mut self, index: i32);
+
+    #[signal]
+    pub fn split_undone(&mut self);
+
+    #[signal]
+    pub fn split_skipped(&mut self);
+
+    #[signal]
+    pub fn paused(&mut self);
+
+    #[signal]
+    pub fn resumed(&mut self);
+
+    #[signal]
+    pub fn run_reset(&mut self, saved: bool);
+
+    #[signal]
+    pub fn run_finished(&mut self, is_pb: bool);
+
+    // The start delay ran out and the timer passed 0, see countdown_remaining
+    #[signal]
+    pub fn countdown_finished(&mut self);
+
+    // Everything the splits need for one frame, read under a single lock.
+    // Times are split times (not segment times) for the given timing method, 0.0 where there is none.
+    #[func]
+    fn get_snapshot(&self, rta: bool) -> Dictionary {
+        let binding = timer_read(&self.timer);
+        let snapshot = binding.snapshot();
+        let method = if rta {
+            TimingMethod::RealTime
+        } else {
+            TimingMethod::GameTime
+        };
+        let comparison = snapshot.current_comparison();
+        let segments = snapshot.run().segments();
+        let seconds = |t: Option<TimeSpan>| t.unwrap_or_default().total_seconds();
+
+        let mut names = PackedStringArray::new();
+        let mut split_times = PackedFloat64Array::new();
+        let mut pb_split_times = PackedFloat64Array::new();
+        let mut best_segments = PackedFloat64Array::new();
+        let mut comparison_times = PackedFloat64Array::new();
+        for segment in segments {
+            names.push(GString::from(segment.name()));
+            split_times.push(seconds(segment.split_time()[method]));
+            pb_split_times.push(seconds(segment.personal_best_split_time()[method]));
+            best_segments.push(seconds(segment.best_segment_time()[method]));
+            comparison_times.push(seconds(segment.comparison(comparison)[method]));
+        }
+
+        let t = snapshot.current_time();
+        let mut dict = Dictionary::new();
+        dict.set("revision", self.revision);
+        dict.set("phase", snapshot.current_phase() as u8);
+        dict.set(
+            "current_split_index",
+            snapshot.current_split_index().map_or(-1, |i| i as i64),
+        );
+        dict.set("current_time", seconds(t.real_time));
+        dict.set("current_game_time", seconds(t.game_time));
+        dict.set("comparison", comparison);
+        dict.set("names", names);
+        dict.set("split_times", split_times);
+        dict.set("pb_split_times", pb_split_times);
+        dict.set("best_segments", best_segments);
+        dict.set("comparison_times", comparison_times);
+        dict
+    }
+
+    // Analysis, always using the current comparison and timing method.
+    // Like the other getters, times that can't be calculated come back as 0.0
+    #[func]
+    fn get_sum_of_best(&self) -> f64 {
+        let binding = timer_read(&self.timer);
+        sum_of_segments::calculate_best(
+            binding.run().segments(),
+            false,
+            false,
+            binding.current_timing_method(),
+        )
+        .unwrap_or_default()
+        .total_seconds()
+    }
+
+    // Current pace against the golds: the attempt's time so far plus the golds of the segments left,
+    // what livesplit calls Best Possible Time
+    #[func]
+    fn get_best_possible_time(&self) -> f64 {
+        let binding = timer_read(&self.timer);
+        current_pace::calculate(&binding.snapshot(), best_segments::NAME)
+            .0
+            .unwrap_or_default()
+            .total_seconds()
+    }
+
+    #[func]
+    fn get_possible_time_save(&self, idx: i32) -> f64 {
+        let binding = timer_read(&self.timer);
+        if idx < 0 || idx as usize >= binding.run().len() {
+            return 0.0;
+        }
+        let snapshot = binding.snapshot();
+        possible_time_save::calculate(&snapshot, idx as usize, snapshot.current_comparison(), false)
+            .0
+            .unwrap_or_default()
+            .total_seconds()
+    }
+
+    // Time that can still be saved over the remaining splits of the current attempt
+    #[func]
+    fn get_total_possible_time_save(&self) -> f64 {
+        let binding = timer_read(&self.timer);
+        let snapshot = binding.snapshot();
+        let idx = snapshot.current_split_index().unwrap_or_default();
+        possible_time_save::calculate_total(&snapshot, idx, snapshot.current_comparison())
+            .0
+            .total_seconds()
+    }
+
+    #[func]
+    fn get_current_pace(&self) -> f64 {
+        let binding = timer_read(&self.timer);
+        let snapshot = binding.snapshot();
+        current_pace::calculate(&snapshot, snapshot.current_comparison())
+            .0
+            .unwrap_or_default()
+            .total_seconds()
+    }
+
+    // Delta of the current attempt against the comparison, live if the current split is behind
+    #[func]
+    fn get_delta(&self) -> f64 {
+        let binding = timer_read(&self.timer);
+        let snapshot = binding.snapshot();
+        delta::calculate(&snapshot, snapshot.current_comparison())
+            .0
+            .unwrap_or_default()
+            .total_seconds()
+    }
+
+    // Delta at a finished split
+    #[func]
+    fn get_split_delta(&self, idx: i32) -> f64 {
+        let binding = timer_read(&self.timer);
+        let method = binding.current_timing_method();
+        let segment = binding.run().segment(idx as usize);
+        match (
+            segment.split_time()[method],
+            segment.comparison(binding.current_comparison())[method],
+        ) {
+            (Some(t), Some(comp)) => (t - comp).total_seconds(),
+            _ => 0.0,
+        }
+    }
+
+    // Between 0 and 1
+    #[func]
+    fn get_pb_chance(&self) -> f64 {
+        let binding = timer_read(&self.timer);
+        pb_chance::for_timer(&binding.snapshot()).0
+    }
+
+    // Layout
+    // Replaces the layout's components, ignoring unknown names. See layout::component_from_name
+    #[func]
+    fn set_layout_components(&mut self, names: PackedStringArray) {
+        let mut new_layout = Layout::new();
+        for name in names.as_slice() {
+            if let Some(component) = layout::component_from_name(&name.to_string()) {
+                new_layout.push(component);
+            }
+        }
+        self.layout = new_layout;
+    }
+
+    #[func]
+    fn get_layout_components(&self) -> PackedStringArray {
+        PackedStringArray::from_iter(
+            self.layout
+                .components
+                .iter()
+                .map(|c| GString::from(layout::component_name(c))),
+        )
+    }
+
+    // State of every component in the layout, in order, as livesplit would render it.
+    // The state is kept between calls and updated in place, so this is fine to call every frame.
+    #[func]
+    fn get_layout_state(&mut self) -> Dictionary {
+        let binding = timer_read(&self.timer);
+        self.layout
+            .update_state(&mut self.layout_state, &mut self.image_cache, &binding.snapshot());
+        layout::layout_state_to_dict(&self.layout_state)
+    }
+
+    // Single component, mostly for elements that only need e.g. the splits. Only that component's state is worked out.
+    #[func]
+    fn get_component_state(&mut self, idx: i32) -> Dictionary {
+        let binding = timer_read(&self.timer);
+        let settings = self.layout.general_settings().clone();
+        let Some(component) = self.layout.components.get_mut(idx as usize) else {
+            return Dictionary::new();
+        };
+        let state = component.state(&mut self.image_cache, &binding.snapshot(), &settings);
+        layout::component_state_to_dict(&state)
+    }
+
+    // Loads a livesplit .lsl layout, replacing the current layout.
+    // Returns the timer settings it maps to (see lsl::layout_to_settings), or an empty dictionary on failure.
+    #[func]
+    fn import_lsl(&mut self, file_path: String) -> Dictionary {
+        let source = match fs::read_to_string(&file_path) {
+            Ok(s) => s,
+            Err(_) => return Dictionary::new(),
+        };
+        match lsl::parse_lsl(&source) {
+            Some(layout) => {
+                let settings = lsl::layout_to_settings(&layout);
+                self.layout = layout;
+                settings
+            }
+            None => Dictionary::new(),
+        }
+    }
+
+    #[func]
+    fn export_lsl(&self, file_path: String, settings: Dictionary) -> bool {
+        fs::write(file_path, lsl::write_lsl(&self.layout, &settings)).is_ok()
+    }
+
     // hotkeys
     #[func]
     fn add_hotkey(&mut self, key_string: String, hotkey_id: i32) -> bool {
@@ -460,7 +898,8 @@ impl DeadSplitTimer {
 
     #[func]
     fn load_wasm_autosplitter(&mut self, path: String) -> bool {
-        self.autosplitter_manager = AutosplitterManager::new(self.timer.clone(), path).ok();
+        self.autosplitter_manager =
+            AutosplitterManager::new(self.timer.clone(), self.events.clone(), path).ok();
         self.autosplitter_manager.is_some()
     }
 
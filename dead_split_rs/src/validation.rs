@@ -0,0 +1,75 @@
+use livesplit_core::{
+    timing::formatter::{Regular, TimeFormatter},
+    Run, Segment, TimeSpan, TimingMethod,
+};
+
+fn format_time(t: TimeSpan) -> String {
+    Regular::new().format(t).to_string()
+}
+
+// Parses times like "1:23:45.67", "23:45" or "45.6". An empty string means no time.
+pub fn parse_time(text: &str) -> Result<Option<TimeSpan>, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+    text.parse::<TimeSpan>()
+        .map(Some)
+        .map_err(|_| format!("\"{text}\" is not a valid time"))
+}
+
+// Comparison names as typed in by the user, without surrounding whitespace
+pub fn comparison_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(String::from("The comparison needs a name"));
+    }
+    Ok(name.to_owned())
+}
+
+// The run editor quietly moves split times that go backwards and golds slower than the PB segment, so
+// times typed in by the user are checked against their neighbours before they're set.
+// Err is the message to show, the time isn't set then.
+pub fn check_split_time(
+    run: &Run,
+    idx: usize,
+    comparison: &str,
+    method: TimingMethod,
+    time: Option<TimeSpan>,
+) -> Result<(), String> {
+    let Some(time) = time else {
+        return Ok(());
+    };
+    let split = |s: &Segment| Some((s.name().to_owned(), s.comparison(comparison)[method]?));
+    if let Some((previous, t)) = run.segments()[..idx].iter().rev().find_map(split) {
+        if time < t {
+            return Err(format!("{} is earlier than \"{previous}\" in {comparison}", format_time(time)));
+        }
+    }
+    if let Some((next, t)) = run.segments()[idx + 1..].iter().find_map(split) {
+        if time > t {
+            return Err(format!("{} is later than \"{next}\" in {comparison}", format_time(time)));
+        }
+    }
+    Ok(())
+}
+
+// Only segments with a PB time on both ends have a PB segment time to compare against
+pub fn check_best_segment_time(run: &Run, idx: usize, method: TimingMethod, time: Option<TimeSpan>) -> Result<(), String> {
+    let start = match idx {
+        0 => Some(TimeSpan::zero()),
+        _ => run.segment(idx - 1).personal_best_split_time()[method],
+    };
+    let end = run.segment(idx).personal_best_split_time()[method];
+    if let (Some(start), Some(end), Some(time)) = (start, end, time) {
+        if time > end - start {
+            return Err(format!(
+                "{} is slower than the PB segment for \"{}\" ({})",
+                format_time(time),
+                run.segment(idx).name(),
+                format_time(end - start)
+            ));
+        }
+    }
+    Ok(())
+}